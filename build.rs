@@ -1,4 +1,7 @@
-use std::{env, fs, path::PathBuf};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
@@ -6,8 +9,21 @@ fn main() {
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let target_dir = out_dir.join("interface_rlib");
-    let target = "nvptx64-nvidia-cuda";
 
+    // only the device backend compiles kernels for the device
+    if env::var_os("CARGO_FEATURE_CUDA").is_some() {
+        build_interface_rlib("nvptx64-nvidia-cuda", &target_dir, &out_dir);
+    }
+
+    // the cpu reference backend links its generated code against an interface rlib for the host
+    let host = env::var("TARGET").unwrap();
+    let host_out_dir = out_dir.join("host");
+    fs::create_dir_all(&host_out_dir).unwrap();
+    build_interface_rlib(&host, &target_dir, &host_out_dir);
+    println!("cargo:rustc-env=HOST_TARGET={host}");
+}
+
+fn build_interface_rlib(target: &str, target_dir: &Path, out_dir: &Path) {
     let output = std::process::Command::new("rustup")
         .env_remove("RUSTFLAGS")
         .env_remove("CARGO_ENCODED_RUSTFLAGS")
@@ -27,13 +43,13 @@ fn main() {
         .arg(target)
         .arg("--crate-type=rlib")
         .arg("--target-dir")
-        .arg(&target_dir)
+        .arg(target_dir)
         .output()
         .unwrap();
 
     assert!(
         output.status.success(),
-        "building interface rlib failed {output:?}"
+        "building interface rlib for {target} failed {output:?}"
     );
 
     let output_dir = target_dir.join(target).join("release");
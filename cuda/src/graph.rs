@@ -133,6 +133,7 @@ impl<'a> Graph<'a> {
         block_height: usize,
         grid_width: usize,
         grid_height: usize,
+        params: &[usize],
     ) -> driver::Result<Node> {
         let node = add_kernel_node_raw(
             self.inner,
@@ -142,6 +143,7 @@ impl<'a> Graph<'a> {
             block_height,
             grid_width,
             grid_height,
+            params,
         )?;
         Ok(Node {
             p: PhantomData,
@@ -268,7 +270,15 @@ fn add_kernel_node_raw(
     block_height: usize,
     grid_width: usize,
     grid_height: usize,
+    params: &[usize],
 ) -> Result<driver::CUgraphNode> {
+    // the driver copies the values when the node is added, so they only need to live until then
+    let mut values = params.to_vec();
+    let mut kernel_params = values
+        .iter_mut()
+        .map(|value| value as *mut usize as *mut c_void)
+        .collect_vec();
+
     let params = driver::CUDA_KERNEL_NODE_PARAMS {
         func: function,
         gridDimX: grid_width as u32,
@@ -278,7 +288,7 @@ fn add_kernel_node_raw(
         blockDimY: block_height as u32,
         blockDimZ: 1,
         sharedMemBytes: 0,
-        kernelParams: ptr::null_mut(),
+        kernelParams: kernel_params.as_mut_ptr(),
         extra: ptr::null_mut(),
         kern: ptr::null_mut(),
         ctx: ptr::null_mut(),
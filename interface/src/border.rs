@@ -0,0 +1,126 @@
+// How the positions outside of an image are read
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BorderMode {
+    // A constant pixel
+    Constant,
+    // The nearest edge pixel
+    Replicate,
+    // The image mirrored at its edge pixels, which are not repeated
+    Reflect,
+    // The image repeated
+    Wrap,
+}
+
+impl BorderMode {
+    // Maps a position along an axis of length n into the image, or returns None if the constant is read instead
+    pub fn position(self, i: isize, n: usize) -> Option<usize> {
+        let n = n as isize;
+        if 0 <= i && i < n {
+            return Some(i as usize);
+        }
+
+        match self {
+            BorderMode::Constant => None,
+            BorderMode::Replicate => Some(if i < 0 { 0 } else { n as usize - 1 }),
+            BorderMode::Reflect if n == 1 => Some(0),
+            BorderMode::Reflect => {
+                let period = 2 * (n - 1);
+                let i = i.rem_euclid(period);
+                Some(if i < n { i } else { period - i } as usize)
+            }
+            BorderMode::Wrap => Some(i.rem_euclid(n) as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BorderMode;
+
+    // The positions of -pad..n + pad along an axis of length n
+    fn positions(mode: BorderMode, n: usize, pad: isize) -> [Option<usize>; 32] {
+        let mut positions = [None; 32];
+        for (i, position) in (-pad..n as isize + pad).zip(positions.iter_mut()) {
+            *position = mode.position(i, n);
+        }
+        positions
+    }
+
+    fn expected(positions: &[usize]) -> [Option<usize>; 32] {
+        let mut expected = [None; 32];
+        for (position, expected) in positions.iter().zip(expected.iter_mut()) {
+            *expected = Some(*position);
+        }
+        expected
+    }
+
+    #[test]
+    fn inside_is_unchanged() {
+        for mode in [
+            BorderMode::Constant,
+            BorderMode::Replicate,
+            BorderMode::Reflect,
+            BorderMode::Wrap,
+        ] {
+            assert_eq!(positions(mode, 4, 0), expected(&[0, 1, 2, 3]));
+        }
+    }
+
+    #[test]
+    fn constant() {
+        let mut expected = expected(&[0, 0, 0, 1, 2, 3]);
+        expected[..2].fill(None);
+        expected[6..8].fill(None);
+        assert_eq!(positions(BorderMode::Constant, 4, 2), expected);
+    }
+
+    #[test]
+    fn replicate() {
+        assert_eq!(
+            positions(BorderMode::Replicate, 4, 2),
+            expected(&[0, 0, 0, 1, 2, 3, 3, 3])
+        );
+    }
+
+    #[test]
+    fn reflect() {
+        assert_eq!(
+            positions(BorderMode::Reflect, 4, 2),
+            expected(&[2, 1, 0, 1, 2, 3, 2, 1])
+        );
+    }
+
+    #[test]
+    fn wrap() {
+        assert_eq!(
+            positions(BorderMode::Wrap, 4, 2),
+            expected(&[2, 3, 0, 1, 2, 3, 0, 1])
+        );
+    }
+
+    #[test]
+    fn padding_wider_than_image() {
+        assert_eq!(
+            positions(BorderMode::Replicate, 3, 5),
+            expected(&[0, 0, 0, 0, 0, 0, 1, 2, 2, 2, 2, 2, 2])
+        );
+        assert_eq!(
+            positions(BorderMode::Reflect, 3, 5),
+            expected(&[1, 0, 1, 2, 1, 0, 1, 2, 1, 0, 1, 2, 1])
+        );
+        assert_eq!(
+            positions(BorderMode::Wrap, 3, 5),
+            expected(&[1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1])
+        );
+        assert_eq!(BorderMode::Constant.position(-5, 3), None);
+        assert_eq!(BorderMode::Constant.position(7, 3), None);
+    }
+
+    #[test]
+    fn single_pixel() {
+        for mode in [BorderMode::Replicate, BorderMode::Reflect, BorderMode::Wrap] {
+            assert_eq!(positions(mode, 1, 3), expected(&[0; 7]));
+        }
+        assert_eq!(BorderMode::Constant.position(-1, 1), None);
+    }
+}
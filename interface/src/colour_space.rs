@@ -0,0 +1,344 @@
+use crate::{
+    math::{floor, powf},
+    Channel, FromPixel, Hsv, Lab, Rgb, YCbCr,
+};
+
+// Rgb pixels are taken to be sRGB encoded, and are converted through their normalized channels
+
+fn normalized<T: Channel>(Rgb { r, g, b }: Rgb<T>) -> Rgb<f32> {
+    Rgb {
+        r: r.to_normalized(),
+        g: g.to_normalized(),
+        b: b.to_normalized(),
+    }
+}
+
+fn denormalized<T: Channel>(Rgb { r, g, b }: Rgb<f32>) -> Rgb<T> {
+    Rgb {
+        r: T::from_normalized(r),
+        g: T::from_normalized(g),
+        b: T::from_normalized(b),
+    }
+}
+
+impl<T: Channel> FromPixel<Rgb<T>> for Hsv<f32> {
+    fn from_pixel(pixel: Rgb<T>) -> Self {
+        let Rgb { r, g, b } = normalized(pixel);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            let h = 60.0 * (g - b) / delta;
+            if h < 0.0 {
+                h + 360.0
+            } else {
+                h
+            }
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+
+        Self { h, s, v: max }
+    }
+}
+
+impl<T: Channel> FromPixel<Hsv<f32>> for Rgb<T> {
+    fn from_pixel(Hsv { h, s, v }: Hsv<f32>) -> Self {
+        // hues outside [0, 360) wrap around
+        let h = (h - 360.0 * floor(h / 360.0)) / 60.0;
+        let c = v * s;
+        // f32::abs is not available in core on the device toolchain
+        let distance = h - 2.0 * floor(h / 2.0) - 1.0;
+        let x = c * (1.0 - if distance < 0.0 { -distance } else { distance });
+        let m = v - c;
+
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        denormalized(Rgb {
+            r: r + m,
+            g: g + m,
+            b: b + m,
+        })
+    }
+}
+
+impl<T: Channel> FromPixel<Rgb<T>> for YCbCr<f32> {
+    fn from_pixel(pixel: Rgb<T>) -> Self {
+        let Rgb { r, g, b } = normalized(pixel);
+        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+        Self {
+            y,
+            cb: 0.5 + (b - y) * (0.5 / 0.886),
+            cr: 0.5 + (r - y) * (0.5 / 0.701),
+        }
+    }
+}
+
+impl YCbCr<f32> {
+    // Expands 8 bit video range components, as in NV12 and I420 frames, where luma is in [16, 235] and chroma in
+    // [16, 240]
+    pub fn from_video_range(y: u8, cb: u8, cr: u8) -> Self {
+        Self {
+            y: (y as f32 - 16.0) / 219.0,
+            cb: (cb as f32 - 16.0) / 224.0,
+            cr: (cr as f32 - 16.0) / 224.0,
+        }
+    }
+}
+
+impl<T: Channel> FromPixel<YCbCr<f32>> for Rgb<T> {
+    fn from_pixel(YCbCr { y, cb, cr }: YCbCr<f32>) -> Self {
+        let (cb, cr) = (cb - 0.5, cr - 0.5);
+        denormalized(Rgb {
+            r: y + 1.402 * cr,
+            g: y - 0.344136 * cb - 0.714136 * cr,
+            b: y + 1.772 * cb,
+        })
+    }
+}
+
+// The D65 white point in XYZ
+const WHITE: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+const DELTA: f32 = 6.0 / 29.0;
+
+fn lab_f(t: f32) -> f32 {
+    if t > DELTA * DELTA * DELTA {
+        powf(t, 1.0 / 3.0)
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inverse(t: f32) -> f32 {
+    if t > DELTA {
+        t * t * t
+    } else {
+        3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
+    }
+}
+
+impl<T: Channel> FromPixel<Rgb<T>> for Lab<f32> {
+    fn from_pixel(pixel: Rgb<T>) -> Self {
+        let Rgb { r, g, b } = normalized(pixel).srgb_to_linear();
+        let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+        let y = 0.2126729 * r + 0.7151522 * g + 0.072175 * b;
+        let z = 0.0193339 * r + 0.119192 * g + 0.9503041 * b;
+
+        let (fx, fy, fz) = (
+            lab_f(x / WHITE[0]),
+            lab_f(y / WHITE[1]),
+            lab_f(z / WHITE[2]),
+        );
+        Self {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+}
+
+impl<T: Channel> FromPixel<Lab<f32>> for Rgb<T> {
+    fn from_pixel(Lab { l, a, b }: Lab<f32>) -> Self {
+        let fy = (l + 16.0) / 116.0;
+        let x = WHITE[0] * lab_f_inverse(fy + a / 500.0);
+        let y = WHITE[1] * lab_f_inverse(fy);
+        let z = WHITE[2] * lab_f_inverse(fy - b / 200.0);
+
+        let linear = Rgb {
+            r: 3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+            g: -0.969266 * x + 1.8760108 * y + 0.041556 * z,
+            b: 0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+        };
+        denormalized(linear.linear_to_srgb())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use crate::{FromPixel, Hsv, IntoPixel, Lab, Rgb, YCbCr};
+
+    // Every 15th value of each channel, including 0 and 255
+    fn colours() -> impl Iterator<Item = Rgb<u8>> {
+        (0..=255).step_by(15).flat_map(|r| {
+            (0..=255)
+                .step_by(15)
+                .flat_map(move |g| (0..=255).step_by(15).map(move |b| Rgb { r, g, b }))
+        })
+    }
+
+    fn rgb(r: u8, g: u8, b: u8) -> Rgb<u8> {
+        Rgb { r, g, b }
+    }
+
+    fn assert_same(result: Rgb<u8>, expected: Rgb<u8>) {
+        assert_eq!(
+            (result.r, result.g, result.b),
+            (expected.r, expected.g, expected.b)
+        );
+    }
+
+    fn assert_near(result: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (result - expected).abs() <= tolerance,
+            "{result} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn hsv_round_trips() {
+        for colour in colours() {
+            let hsv: Hsv<f32> = colour.into_pixel();
+            assert_same(hsv.into_pixel(), colour);
+        }
+    }
+
+    #[test]
+    fn ycbcr_round_trips() {
+        for colour in colours() {
+            let ycbcr: YCbCr<f32> = colour.into_pixel();
+            assert_same(ycbcr.into_pixel(), colour);
+        }
+    }
+
+    #[test]
+    fn lab_round_trips() {
+        for colour in colours() {
+            let lab: Lab<f32> = colour.into_pixel();
+            assert_same(lab.into_pixel(), colour);
+        }
+    }
+
+    // Without the rounding of u8, the error of the approximated powf shows
+    #[test]
+    fn f32_round_trips_within_1e_4() {
+        for Rgb { r, g, b } in colours() {
+            let colour = Rgb {
+                r: r as f32 / 255.0,
+                g: g as f32 / 255.0,
+                b: b as f32 / 255.0,
+            };
+            let hsv: Rgb<f32> = Hsv::from_pixel(colour).into_pixel();
+            let ycbcr: Rgb<f32> = YCbCr::from_pixel(colour).into_pixel();
+            let lab: Rgb<f32> = Lab::from_pixel(colour).into_pixel();
+            for result in [hsv, ycbcr, lab] {
+                assert_near(result.r, colour.r, 1e-4);
+                assert_near(result.g, colour.g, 1e-4);
+                assert_near(result.b, colour.b, 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn hue_of_primaries() {
+        for (colour, hue) in [
+            (rgb(255, 0, 0), 0.0),
+            (rgb(255, 255, 0), 60.0),
+            (rgb(0, 255, 0), 120.0),
+            (rgb(0, 255, 255), 180.0),
+            (rgb(0, 0, 255), 240.0),
+            (rgb(255, 0, 255), 300.0),
+        ] {
+            let Hsv { h, s, v } = Hsv::from_pixel(colour);
+            assert_near(h, hue, 1e-4);
+            assert_near(s, 1.0, 1e-6);
+            assert_near(v, 1.0, 1e-6);
+        }
+    }
+
+    #[test]
+    fn hsv_of_grey() {
+        let Hsv { h, s, v } = Hsv::from_pixel(rgb(51, 51, 51));
+        assert_eq!((h, s), (0.0, 0.0));
+        assert_near(v, 0.2, 1e-6);
+    }
+
+    #[test]
+    fn hue_wraps_around() {
+        let red = Hsv {
+            h: 360.0,
+            s: 1.0,
+            v: 1.0,
+        };
+        assert_same(red.into_pixel(), rgb(255, 0, 0));
+        let blue = Hsv {
+            h: -120.0,
+            s: 1.0,
+            v: 1.0,
+        };
+        assert_same(blue.into_pixel(), rgb(0, 0, 255));
+    }
+
+    #[test]
+    fn ycbcr_of_references() {
+        for (colour, (y, cb, cr)) in [
+            (rgb(0, 0, 0), (0.0, 0.5, 0.5)),
+            (rgb(255, 255, 255), (1.0, 0.5, 0.5)),
+            (rgb(255, 0, 0), (0.299, 0.5 - 0.299 * 0.5 / 0.886, 1.0)),
+            (rgb(0, 0, 255), (0.114, 1.0, 0.5 - 0.114 * 0.5 / 0.701)),
+        ] {
+            let result = YCbCr::from_pixel(colour);
+            assert_near(result.y, y, 1e-6);
+            assert_near(result.cb, cb, 1e-6);
+            assert_near(result.cr, cr, 1e-6);
+        }
+    }
+
+    #[test]
+    fn lab_of_references() {
+        // CIELAB with the D65 white point, as given by e.g. colormine.org
+        for (colour, (l, a, b)) in [
+            (rgb(255, 255, 255), (100.0, 0.0, 0.0)),
+            (rgb(0, 0, 0), (0.0, 0.0, 0.0)),
+            (rgb(255, 0, 0), (53.2408, 80.0925, 67.2032)),
+            (rgb(0, 255, 0), (87.7347, -86.1827, 83.1793)),
+            (rgb(0, 0, 255), (32.2970, 79.1875, -107.8602)),
+        ] {
+            let result = Lab::from_pixel(colour);
+            assert_near(result.l, l, 1e-2);
+            assert_near(result.a, a, 1e-2);
+            assert_near(result.b, b, 1e-2);
+        }
+    }
+
+    #[test]
+    fn video_range_endpoints() {
+        let black = YCbCr::from_video_range(16, 16, 16);
+        assert_eq!((black.y, black.cb, black.cr), (0.0, 0.0, 0.0));
+        let white = YCbCr::from_video_range(235, 240, 240);
+        assert_eq!((white.y, white.cb, white.cr), (1.0, 1.0, 1.0));
+        let grey = YCbCr::from_video_range(16, 128, 128);
+        assert_eq!((grey.cb, grey.cr), (0.5, 0.5));
+
+        assert_same(
+            YCbCr::from_video_range(16, 128, 128).into_pixel(),
+            rgb(0, 0, 0),
+        );
+        assert_same(
+            YCbCr::from_video_range(235, 128, 128).into_pixel(),
+            rgb(255, 255, 255),
+        );
+        // below and above the video range saturates
+        assert_same(
+            YCbCr::from_video_range(0, 128, 128).into_pixel(),
+            rgb(0, 0, 0),
+        );
+        assert_same(
+            YCbCr::from_video_range(255, 128, 128).into_pixel(),
+            rgb(255, 255, 255),
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{math::powf, Luma, Rgb, Rgba};
+
+// A scalar pixels can be made of. Integer channels span [0, 1] over their whole range, while f32 channels are taken as is
+// and may be outside [0, 1].
+pub trait Channel: Copy {
+    // The channel value of full intensity, which is also the alpha of an opaque pixel
+    const MAX: Self;
+
+    fn to_normalized(self) -> f32;
+
+    // Rounds to the nearest value, saturating values outside the range of the channel. NaN becomes 0.
+    fn from_normalized(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    const MAX: Self = u8::MAX;
+
+    fn to_normalized(self) -> f32 {
+        self as f32 / u8::MAX as f32
+    }
+
+    fn from_normalized(value: f32) -> Self {
+        // float to integer casts saturate, so only the rounding is needed
+        (value * u8::MAX as f32 + 0.5) as u8
+    }
+}
+
+impl Channel for u16 {
+    const MAX: Self = u16::MAX;
+
+    fn to_normalized(self) -> f32 {
+        self as f32 / u16::MAX as f32
+    }
+
+    fn from_normalized(value: f32) -> Self {
+        (value * u16::MAX as f32 + 0.5) as u16
+    }
+}
+
+impl Channel for f32 {
+    const MAX: Self = 1.0;
+
+    fn to_normalized(self) -> f32 {
+        self
+    }
+
+    fn from_normalized(value: f32) -> Self {
+        value
+    }
+}
+
+// Converts an sRGB encoded channel in [0, 1] to linear light
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        powf((value + 0.055) / 1.055, 2.4)
+    }
+}
+
+// Converts a linear light channel in [0, 1] to sRGB encoding
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * powf(value, 1.0 / 2.4) - 0.055
+    }
+}
+
+// Conversion between any two supported pixel types, which From cannot express generically since it would overlap with
+// the reflexive From<T> for T. Channels are converted through their normalized value, alpha is dropped or made opaque,
+// and colour is reduced to its luma.
+pub trait FromPixel<P> {
+    fn from_pixel(pixel: P) -> Self;
+}
+
+pub trait IntoPixel<P> {
+    fn into_pixel(self) -> P;
+}
+
+impl<P, Q: FromPixel<P>> IntoPixel<Q> for P {
+    fn into_pixel(self) -> Q {
+        Q::from_pixel(self)
+    }
+}
+
+fn convert<T: Channel, U: Channel>(value: T) -> U {
+    U::from_normalized(value.to_normalized())
+}
+
+impl<T: Channel, U: Channel> FromPixel<Rgb<T>> for Rgb<U> {
+    fn from_pixel(Rgb { r, g, b }: Rgb<T>) -> Self {
+        Self {
+            r: convert(r),
+            g: convert(g),
+            b: convert(b),
+        }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Rgba<T>> for Rgb<U> {
+    fn from_pixel(Rgba { r, g, b, .. }: Rgba<T>) -> Self {
+        Self {
+            r: convert(r),
+            g: convert(g),
+            b: convert(b),
+        }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Luma<T>> for Rgb<U> {
+    fn from_pixel(Luma { l }: Luma<T>) -> Self {
+        let l = convert(l);
+        Self { r: l, g: l, b: l }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Rgba<T>> for Rgba<U> {
+    fn from_pixel(Rgba { r, g, b, a }: Rgba<T>) -> Self {
+        Self {
+            r: convert(r),
+            g: convert(g),
+            b: convert(b),
+            a: convert(a),
+        }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Rgb<T>> for Rgba<U> {
+    fn from_pixel(Rgb { r, g, b }: Rgb<T>) -> Self {
+        Self {
+            r: convert(r),
+            g: convert(g),
+            b: convert(b),
+            a: U::MAX,
+        }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Luma<T>> for Rgba<U> {
+    fn from_pixel(Luma { l }: Luma<T>) -> Self {
+        let l = convert(l);
+        Self {
+            r: l,
+            g: l,
+            b: l,
+            a: U::MAX,
+        }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Luma<T>> for Luma<U> {
+    fn from_pixel(Luma { l }: Luma<T>) -> Self {
+        Self { l: convert(l) }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Rgb<T>> for Luma<U> {
+    fn from_pixel(pixel: Rgb<T>) -> Self {
+        Self {
+            l: U::from_normalized(pixel.luma()),
+        }
+    }
+}
+
+impl<T: Channel, U: Channel> FromPixel<Rgba<T>> for Luma<U> {
+    fn from_pixel(pixel: Rgba<T>) -> Self {
+        Self::from_pixel(Rgb::<f32>::from_pixel(pixel))
+    }
+}
+
+impl Rgb<f32> {
+    // Clamps every channel to [0, 1]
+    pub fn saturate(self) -> Self {
+        self.clamp(
+            Self::default(),
+            Self {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+            },
+        )
+    }
+
+    pub fn srgb_to_linear(self) -> Self {
+        Self {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+        }
+    }
+
+    pub fn linear_to_srgb(self) -> Self {
+        Self {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+        }
+    }
+}
+
+// Alpha is linear in both encodings, so it is left as is
+impl Rgba<f32> {
+    pub fn srgb_to_linear(self) -> Self {
+        Self {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+            a: self.a,
+        }
+    }
+
+    pub fn linear_to_srgb(self) -> Self {
+        Self {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a,
+        }
+    }
+}
+
+impl Luma<f32> {
+    pub fn srgb_to_linear(self) -> Self {
+        Self {
+            l: srgb_to_linear(self.l),
+        }
+    }
+
+    pub fn linear_to_srgb(self) -> Self {
+        Self {
+            l: linear_to_srgb(self.l),
+        }
+    }
+}
+
+// The From conversions between integer and f32 pixels, which kernels use through into(). Like from_pixel, they round and
+// saturate when converting to integers.
+
+impl From<Rgb<u8>> for Rgb<f32> {
+    fn from(value: Rgb<u8>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Rgb<f32>> for Rgb<u8> {
+    fn from(value: Rgb<f32>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Rgb<u16>> for Rgb<f32> {
+    fn from(value: Rgb<u16>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Rgb<f32>> for Rgb<u16> {
+    fn from(value: Rgb<f32>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Rgba<u8>> for Rgba<f32> {
+    fn from(value: Rgba<u8>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Rgba<f32>> for Rgba<u8> {
+    fn from(value: Rgba<f32>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Luma<u8>> for Luma<f32> {
+    fn from(value: Luma<u8>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Luma<f32>> for Luma<u8> {
+    fn from(value: Luma<f32>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Luma<u16>> for Luma<f32> {
+    fn from(value: Luma<u16>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+impl From<Luma<f32>> for Luma<u16> {
+    fn from(value: Luma<f32>) -> Self {
+        Self::from_pixel(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{linear_to_srgb, srgb_to_linear, Channel};
+
+    // The transfer functions in f64 as the references
+    fn reference_srgb_to_linear(value: f64) -> f64 {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn reference_linear_to_srgb(value: f64) -> f64 {
+        if value <= 0.0031308 {
+            value * 12.92
+        } else {
+            1.055 * value.powf(1.0 / 2.4) - 0.055
+        }
+    }
+
+    #[test]
+    fn srgb_transfer_is_accurate_to_1e_6() {
+        for i in 0..=100_000 {
+            let value = i as f32 / 100_000.0;
+            let to_linear = srgb_to_linear(value) as f64 - reference_srgb_to_linear(value as f64);
+            let to_srgb = linear_to_srgb(value) as f64 - reference_linear_to_srgb(value as f64);
+            assert!(
+                to_linear.abs() < 1e-6,
+                "srgb_to_linear({value}) is off by {to_linear:e}"
+            );
+            assert!(
+                to_srgb.abs() < 1e-6,
+                "linear_to_srgb({value}) is off by {to_srgb:e}"
+            );
+        }
+    }
+
+    #[test]
+    fn srgb_transfer_round_trips_u8() {
+        for value in 0..=u8::MAX {
+            let linear = srgb_to_linear(value.to_normalized());
+            assert_eq!(u8::from_normalized(linear_to_srgb(linear)), value);
+        }
+    }
+
+    #[test]
+    fn u8_round_trips() {
+        for value in 0..=u8::MAX {
+            assert_eq!(u8::from_normalized(value.to_normalized()), value);
+        }
+    }
+
+    #[test]
+    fn u16_round_trips() {
+        for value in 0..=u16::MAX {
+            assert_eq!(u16::from_normalized(value.to_normalized()), value);
+        }
+    }
+
+    #[test]
+    fn from_normalized_rounds_to_nearest() {
+        assert_eq!(u8::from_normalized(0.999), 255);
+        assert_eq!(u8::from_normalized(0.001), 0);
+        assert_eq!(u8::from_normalized(0.5), 128);
+        assert_eq!(u16::from_normalized(0.999995), u16::MAX);
+    }
+
+    #[test]
+    fn from_normalized_saturates() {
+        assert_eq!(u8::from_normalized(1.5), 255);
+        assert_eq!(u8::from_normalized(-0.5), 0);
+        assert_eq!(u8::from_normalized(f32::INFINITY), 255);
+        assert_eq!(u8::from_normalized(f32::NEG_INFINITY), 0);
+        assert_eq!(u16::from_normalized(2.0), u16::MAX);
+        assert_eq!(u16::from_normalized(-1.0), 0);
+    }
+
+    #[test]
+    fn nan_becomes_zero() {
+        assert_eq!(u8::from_normalized(f32::NAN), 0);
+        assert_eq!(u16::from_normalized(f32::NAN), 0);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{CbCr, Channel, Hsv, Lab, Luma, Rgb, Rgba, YCbCr};
+
+// Pixels that can be blended, by summing weighted pixels whose weights add up to one. The sum is kept in f32 with the
+// normalized channels, so integer pixels are only rounded once. Every channel is blended on its own, so hues are not
+// interpolated around the colour wheel.
+pub trait Interpolate: Copy {
+    type Sum: Copy + Default;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum;
+
+    fn from_sum(sum: Self::Sum) -> Self;
+}
+
+impl<T: Channel> Interpolate for Rgb<T> {
+    type Sum = Rgb<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        Rgb {
+            r: sum.r + pixel.r.to_normalized() * weight,
+            g: sum.g + pixel.g.to_normalized() * weight,
+            b: sum.b + pixel.b.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            r: T::from_normalized(sum.r),
+            g: T::from_normalized(sum.g),
+            b: T::from_normalized(sum.b),
+        }
+    }
+}
+
+impl<T: Channel> Interpolate for Rgba<T> {
+    type Sum = Rgba<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        Rgba {
+            r: sum.r + pixel.r.to_normalized() * weight,
+            g: sum.g + pixel.g.to_normalized() * weight,
+            b: sum.b + pixel.b.to_normalized() * weight,
+            a: sum.a + pixel.a.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            r: T::from_normalized(sum.r),
+            g: T::from_normalized(sum.g),
+            b: T::from_normalized(sum.b),
+            a: T::from_normalized(sum.a),
+        }
+    }
+}
+
+impl<T: Channel> Interpolate for Luma<T> {
+    type Sum = Luma<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        Luma {
+            l: sum.l + pixel.l.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            l: T::from_normalized(sum.l),
+        }
+    }
+}
+
+impl<T: Channel> Interpolate for CbCr<T> {
+    type Sum = CbCr<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        CbCr {
+            cb: sum.cb + pixel.cb.to_normalized() * weight,
+            cr: sum.cr + pixel.cr.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            cb: T::from_normalized(sum.cb),
+            cr: T::from_normalized(sum.cr),
+        }
+    }
+}
+
+impl<T: Channel> Interpolate for Hsv<T> {
+    type Sum = Hsv<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        Hsv {
+            h: sum.h + pixel.h.to_normalized() * weight,
+            s: sum.s + pixel.s.to_normalized() * weight,
+            v: sum.v + pixel.v.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            h: T::from_normalized(sum.h),
+            s: T::from_normalized(sum.s),
+            v: T::from_normalized(sum.v),
+        }
+    }
+}
+
+impl<T: Channel> Interpolate for YCbCr<T> {
+    type Sum = YCbCr<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        YCbCr {
+            y: sum.y + pixel.y.to_normalized() * weight,
+            cb: sum.cb + pixel.cb.to_normalized() * weight,
+            cr: sum.cr + pixel.cr.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            y: T::from_normalized(sum.y),
+            cb: T::from_normalized(sum.cb),
+            cr: T::from_normalized(sum.cr),
+        }
+    }
+}
+
+impl<T: Channel> Interpolate for Lab<T> {
+    type Sum = Lab<f32>;
+
+    fn add_weighted(sum: Self::Sum, pixel: Self, weight: f32) -> Self::Sum {
+        Lab {
+            l: sum.l + pixel.l.to_normalized() * weight,
+            a: sum.a + pixel.a.to_normalized() * weight,
+            b: sum.b + pixel.b.to_normalized() * weight,
+        }
+    }
+
+    fn from_sum(sum: Self::Sum) -> Self {
+        Self {
+            l: T::from_normalized(sum.l),
+            a: T::from_normalized(sum.a),
+            b: T::from_normalized(sum.b),
+        }
+    }
+}
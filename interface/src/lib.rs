@@ -1,15 +1,23 @@
 #![no_std]
 #![feature(asm_experimental_arch)]
 
+mod border;
+mod colour_space;
+mod conversion;
+mod interpolate;
+mod math;
 mod patch;
 mod shared_memory;
 
-pub use patch::Patch;
+pub use border::BorderMode;
+pub use conversion::{linear_to_srgb, srgb_to_linear, Channel, FromPixel, IntoPixel};
+pub use interpolate::Interpolate;
+pub use patch::{Patch, PatchIter};
 pub use shared_memory::SharedMemory;
 
 use core::{
     marker::PhantomData,
-    ops::{Add, AddAssign, Index, IndexMut, Mul},
+    ops::{Add, AddAssign, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 
 pub struct Image<P> {
@@ -92,19 +100,75 @@ pub struct Rgb<T> {
     pub b: T,
 }
 
-impl Mul<f32> for Rgb<f32> {
+impl<T: Copy + PartialOrd> Rgb<T> {
+    pub fn min(self, other: Self) -> Self {
+        let min = |a: T, b: T| if b < a { b } else { a };
+        Self {
+            r: min(self.r, other.r),
+            g: min(self.g, other.g),
+            b: min(self.b, other.b),
+        }
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        let max = |a: T, b: T| if b > a { b } else { a };
+        Self {
+            r: max(self.r, other.r),
+            g: max(self.g, other.g),
+            b: max(self.b, other.b),
+        }
+    }
+
+    // Clamps every channel to [min, max] of the corresponding channels
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        self.max(min).min(max)
+    }
+}
+
+impl<T: Copy + PartialOrd + Default + Neg<Output = T>> Rgb<T> {
+    pub fn abs(self) -> Self {
+        // f32::abs is not available in core on the device toolchain
+        let abs = |a: T| if a < T::default() { -a } else { a };
+        Self {
+            r: abs(self.r),
+            g: abs(self.g),
+            b: abs(self.b),
+        }
+    }
+}
+
+impl<T: Mul<Output = T> + Add<Output = T>> Rgb<T> {
+    pub fn dot(self, Self { r, g, b }: Self) -> T {
+        self.r * r + self.g * g + self.b * b
+    }
+}
+
+impl<T: Channel> Rgb<T> {
+    // Luma with the Rec. 709 weights, in [0, 1] for channels in their nominal range. The weights are applied to the
+    // sRGB encoded channels, as for video and the image crate, so this is not the relative luminance of the colour,
+    // which weights the channels after linearising them with srgb_to_linear.
+    pub fn luma(self) -> f32 {
+        Rgb::<f32>::from_pixel(self).dot(Rgb {
+            r: 0.2126,
+            g: 0.7152,
+            b: 0.0722,
+        })
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Rgb<T> {
     type Output = Self;
 
-    fn mul(self, rhs: f32) -> Self::Output {
+    fn neg(self) -> Self::Output {
         Self {
-            r: self.r * rhs,
-            g: self.g * rhs,
-            b: self.b * rhs,
+            r: -self.r,
+            g: -self.g,
+            b: -self.b,
         }
     }
 }
 
-impl Add for Rgb<f32> {
+impl<T: Add<Output = T>> Add for Rgb<T> {
     type Output = Self;
 
     fn add(self, Self { r, g, b }: Self) -> Self::Output {
@@ -116,7 +180,56 @@ impl Add for Rgb<f32> {
     }
 }
 
-impl AddAssign for Rgb<f32> {
+impl<T: Sub<Output = T>> Sub for Rgb<T> {
+    type Output = Self;
+
+    fn sub(self, Self { r, g, b }: Self) -> Self::Output {
+        Self {
+            r: self.r - r,
+            g: self.g - g,
+            b: self.b - b,
+        }
+    }
+}
+
+// Component-wise product
+impl<T: Mul<Output = T>> Mul for Rgb<T> {
+    type Output = Self;
+
+    fn mul(self, Self { r, g, b }: Self) -> Self::Output {
+        Self {
+            r: self.r * r,
+            g: self.g * g,
+            b: self.b * b,
+        }
+    }
+}
+
+impl<T: Mul<Output = T> + Copy> Mul<T> for Rgb<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        Self {
+            r: self.r * rhs,
+            g: self.g * rhs,
+            b: self.b * rhs,
+        }
+    }
+}
+
+impl<T: Div<Output = T> + Copy> Div<T> for Rgb<T> {
+    type Output = Self;
+
+    fn div(self, rhs: T) -> Self::Output {
+        Self {
+            r: self.r / rhs,
+            g: self.g / rhs,
+            b: self.b / rhs,
+        }
+    }
+}
+
+impl<T: AddAssign> AddAssign for Rgb<T> {
     fn add_assign(&mut self, Self { r, g, b }: Self) {
         self.r += r;
         self.g += g;
@@ -124,22 +237,306 @@ impl AddAssign for Rgb<f32> {
     }
 }
 
-impl From<Rgb<u8>> for Rgb<f32> {
-    fn from(value: Rgb<u8>) -> Self {
+impl<T: SubAssign> SubAssign for Rgb<T> {
+    fn sub_assign(&mut self, Self { r, g, b }: Self) {
+        self.r -= r;
+        self.g -= g;
+        self.b -= b;
+    }
+}
+
+impl<T: MulAssign> MulAssign for Rgb<T> {
+    fn mul_assign(&mut self, Self { r, g, b }: Self) {
+        self.r *= r;
+        self.g *= g;
+        self.b *= b;
+    }
+}
+
+impl<T: MulAssign + Copy> MulAssign<T> for Rgb<T> {
+    fn mul_assign(&mut self, rhs: T) {
+        self.r *= rhs;
+        self.g *= rhs;
+        self.b *= rhs;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Rgba<T> {
+    pub r: T,
+    pub g: T,
+    pub b: T,
+    pub a: T,
+}
+
+impl Rgba<f32> {
+    // Composites self over below, with straight (not premultiplied) alpha
+    pub fn over(self, below: Self) -> Self {
+        let a = self.a + below.a * (1.0 - self.a);
+        if a == 0.0 {
+            return Self::default();
+        }
+
+        let blend = |top: f32, bottom: f32| (top * self.a + bottom * below.a * (1.0 - self.a)) / a;
         Self {
-            r: value.r as f32 / u8::MAX as f32,
-            g: value.g as f32 / u8::MAX as f32,
-            b: value.b as f32 / u8::MAX as f32,
+            r: blend(self.r, below.r),
+            g: blend(self.g, below.g),
+            b: blend(self.b, below.b),
+            a,
         }
     }
 }
 
-impl From<Rgb<f32>> for Rgb<u8> {
-    fn from(value: Rgb<f32>) -> Self {
+impl Mul<f32> for Rgba<f32> {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
         Self {
-            r: (value.r * u8::MAX as f32) as u8,
-            g: (value.g * u8::MAX as f32) as u8,
-            b: (value.b * u8::MAX as f32) as u8,
+            r: self.r * rhs,
+            g: self.g * rhs,
+            b: self.b * rhs,
+            a: self.a * rhs,
         }
     }
 }
+
+impl Add for Rgba<f32> {
+    type Output = Self;
+
+    fn add(self, Self { r, g, b, a }: Self) -> Self::Output {
+        Self {
+            r: self.r + r,
+            g: self.g + g,
+            b: self.b + b,
+            a: self.a + a,
+        }
+    }
+}
+
+impl AddAssign for Rgba<f32> {
+    fn add_assign(&mut self, Self { r, g, b, a }: Self) {
+        self.r += r;
+        self.g += g;
+        self.b += b;
+        self.a += a;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Luma<T> {
+    pub l: T,
+}
+
+impl Mul<f32> for Luma<f32> {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self { l: self.l * rhs }
+    }
+}
+
+impl Add for Luma<f32> {
+    type Output = Self;
+
+    fn add(self, Self { l }: Self) -> Self::Output {
+        Self { l: self.l + l }
+    }
+}
+
+impl AddAssign for Luma<f32> {
+    fn add_assign(&mut self, Self { l }: Self) {
+        self.l += l;
+    }
+}
+
+// Hue in degrees in [0, 360), saturation and value in [0, 1]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Hsv<T> {
+    pub h: T,
+    pub s: T,
+    pub v: T,
+}
+
+// Full range BT.601 YCbCr as used by JPEG, with all components in [0, 1] and chroma centered on 0.5
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct YCbCr<T> {
+    pub y: T,
+    pub cb: T,
+    pub cr: T,
+}
+
+// The interleaved chroma plane of an NV12 frame
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct CbCr<T> {
+    pub cb: T,
+    pub cr: T,
+}
+
+// CIELAB relative to the D65 white point, with lightness in [0, 100]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Lab<T> {
+    pub l: T,
+    pub a: T,
+    pub b: T,
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use crate::{Rgb, Rgba};
+
+    fn rgb<T>(r: T, g: T, b: T) -> Rgb<T> {
+        Rgb { r, g, b }
+    }
+
+    fn channels<T>(Rgb { r, g, b }: Rgb<T>) -> (T, T, T) {
+        (r, g, b)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = rgb(6i32, -4, 9);
+        let b = rgb(2i32, 3, -1);
+        assert_eq!(channels(-a), (-6, 4, -9));
+        assert_eq!(channels(a + b), (8, -1, 8));
+        assert_eq!(channels(a - b), (4, -7, 10));
+        assert_eq!(channels(a * b), (12, -12, -9));
+        assert_eq!(channels(a * 3), (18, -12, 27));
+        assert_eq!(channels(a / 2), (3, -2, 4));
+    }
+
+    #[test]
+    fn assignments() {
+        let b = rgb(2i32, 3, -1);
+
+        let mut a = rgb(6i32, -4, 9);
+        a += b;
+        assert_eq!(channels(a), (8, -1, 8));
+
+        let mut a = rgb(6i32, -4, 9);
+        a -= b;
+        assert_eq!(channels(a), (4, -7, 10));
+
+        let mut a = rgb(6i32, -4, 9);
+        a *= b;
+        assert_eq!(channels(a), (12, -12, -9));
+
+        let mut a = rgb(6i32, -4, 9);
+        a *= 3;
+        assert_eq!(channels(a), (18, -12, 27));
+    }
+
+    #[test]
+    fn min_max_clamp() {
+        let a = rgb(1.0f32, 5.0, -2.0);
+        let b = rgb(3.0f32, 4.0, -2.5);
+        assert_eq!(channels(a.min(b)), (1.0, 4.0, -2.5));
+        assert_eq!(channels(a.max(b)), (3.0, 5.0, -2.0));
+        assert_eq!(
+            channels(a.clamp(rgb(2.0, 0.0, -1.0), rgb(4.0, 4.5, 1.0))),
+            (2.0, 4.5, -1.0)
+        );
+        assert_eq!(channels(a.abs()), (1.0, 5.0, 2.0));
+    }
+
+    #[test]
+    fn dot() {
+        assert_eq!(rgb(1i32, 2, 3).dot(rgb(4, -5, 6)), 12);
+    }
+
+    #[test]
+    fn luma_of_references() {
+        assert_eq!(rgb(0u8, 0, 0).luma(), 0.0);
+        assert!((rgb(255u8, 255, 255).luma() - 1.0).abs() < 1e-6);
+        assert!((rgb(255u8, 0, 0).luma() - 0.2126).abs() < 1e-6);
+        assert!((rgb(0u8, 255, 0).luma() - 0.7152).abs() < 1e-6);
+        assert!((rgb(0u8, 0, 255).luma() - 0.0722).abs() < 1e-6);
+        // the encoded mid grey, whose relative luminance would be about 0.21
+        assert!((rgb(0.5f32, 0.5, 0.5).luma() - 0.5).abs() < 1e-6);
+    }
+
+    fn rgba_channels(Rgba { r, g, b, a }: Rgba<f32>) -> (f32, f32, f32, f32) {
+        (r, g, b, a)
+    }
+
+    #[test]
+    fn opaque_over_hides_below() {
+        let top = Rgba {
+            r: 0.2,
+            g: 0.4,
+            b: 0.6,
+            a: 1.0,
+        };
+        let below = Rgba {
+            r: 1.0,
+            g: 0.0,
+            b: 0.5,
+            a: 0.7,
+        };
+        assert_eq!(rgba_channels(top.over(below)), (0.2, 0.4, 0.6, 1.0));
+    }
+
+    #[test]
+    fn transparent_over_shows_below() {
+        let top = Rgba {
+            r: 0.2,
+            g: 0.4,
+            b: 0.6,
+            a: 0.0,
+        };
+        let below = Rgba {
+            r: 1.0,
+            g: 0.0,
+            b: 0.5,
+            a: 0.7,
+        };
+        assert_eq!(rgba_channels(top.over(below)), (1.0, 0.0, 0.5, 0.7));
+        // nothing over nothing is transparent black rather than a division by zero
+        let empty = Rgba {
+            r: 0.3,
+            g: 0.3,
+            b: 0.3,
+            a: 0.0,
+        };
+        assert_eq!(rgba_channels(empty.over(empty)), (0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn half_transparent_over() {
+        let top = Rgba {
+            r: 1.0,
+            g: 0.0,
+            b: 0.5,
+            a: 0.5,
+        };
+
+        // over an opaque colour the channels are averaged
+        let opaque = Rgba {
+            r: 0.0,
+            g: 1.0,
+            b: 0.5,
+            a: 1.0,
+        };
+        assert_eq!(rgba_channels(top.over(opaque)), (0.5, 0.5, 0.5, 1.0));
+
+        // over another half transparent colour the top contributes 2/3 of the colour
+        let half = Rgba {
+            r: 0.0,
+            g: 1.0,
+            b: 0.5,
+            a: 0.5,
+        };
+        let (r, g, b, a) = rgba_channels(top.over(half));
+        assert!((r - 2.0 / 3.0).abs() < 1e-6);
+        assert!((g - 1.0 / 3.0).abs() < 1e-6);
+        assert!((b - 0.5).abs() < 1e-6);
+        assert_eq!(a, 0.75);
+    }
+}
@@ -0,0 +1,102 @@
+// core has no transcendental float functions, and the device has no libm to link against, so the few needed are
+// approximated here. Over [0, 1] they are accurate to 1e-6, which is well below the precision of 16 bit channels.
+
+use core::f32::consts::LN_2;
+
+// Only valid for x in the range of i32
+pub(crate) fn floor(x: f32) -> f32 {
+    let i = x as i32 as f32;
+    if i > x {
+        i - 1.0
+    } else {
+        i
+    }
+}
+
+// log2 of a positive, normal x
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    // the mantissa in [1, 2)
+    let m = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+
+    // log2(m) = 2 / ln(2) * atanh(s) with s = (m - 1) / (m + 1) in [0, 1/3)
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let atanh = s
+        * (1.0
+            + s2 * (1.0 / 3.0
+                + s2 * (1.0 / 5.0
+                    + s2 * (1.0 / 7.0
+                        + s2 * (1.0 / 9.0 + s2 * (1.0 / 11.0 + s2 * (1.0 / 13.0)))))));
+    exponent as f32 + 2.0 / LN_2 * atanh
+}
+
+fn exp2(x: f32) -> f32 {
+    if x < -126.0 {
+        return 0.0;
+    }
+    if x > 127.0 {
+        return f32::INFINITY;
+    }
+
+    // x = i + f with f in [0, 1)
+    let i = floor(x);
+    let f = (x - i) * LN_2;
+    let i = i as i32;
+
+    // exp(f) as a Taylor series, f is in [0, ln(2))
+    let mut term = 1.0;
+    let mut exp = 1.0;
+    for n in 1..10 {
+        term *= f / n as f32;
+        exp += term;
+    }
+    exp * f32::from_bits(((i + 127) as u32) << 23)
+}
+
+// x to the power of e, for x >= 0
+pub(crate) fn powf(x: f32, e: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    exp2(e * log2(x))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{floor, powf};
+
+    #[test]
+    fn floor_rounds_down() {
+        for (x, expected) in [
+            (2.5, 2.0),
+            (2.0, 2.0),
+            (-2.5, -3.0),
+            (-2.0, -2.0),
+            (-0.5, -1.0),
+        ] {
+            assert_eq!(floor(x), expected);
+        }
+    }
+
+    #[test]
+    fn powf_is_accurate_to_1e_6() {
+        // the exponents of the sRGB transfer functions and of Lab
+        for e in [2.4, 1.0 / 2.4, 1.0 / 3.0] {
+            for i in 1..=100_000 {
+                let x = i as f32 / 100_000.0;
+                let expected = (x as f64).powf(e as f64);
+                let error = (powf(x, e) as f64 - expected).abs();
+                assert!(error < 1e-6, "powf({x}, {e}) is off by {error:e}");
+            }
+        }
+    }
+
+    #[test]
+    fn powf_of_zero() {
+        assert_eq!(powf(0.0, 2.4), 0.0);
+    }
+}
@@ -1,32 +1,112 @@
 use crate::SharedMemory;
 
-pub struct Patch<const N: usize, T: SharedMemory> {
+// The W x H neighbourhood of a pixel, which is at its centre. Both dimensions are odd. Patches have no anchor, so a
+// window that is not centred on the pixel, such as the pixel and its right neighbour, is read from the smallest centred
+// patch containing it, here a 3x1 patch read at dx of 0 and 1.
+pub struct Patch<const W: usize, const H: usize, T: SharedMemory> {
     shared: *const T,
-    block_width: usize,
+    tile_width: usize,
     base_col: usize,
     base_row: usize,
 }
 
-impl<const N: usize, T: SharedMemory> Patch<N, T> {
-    #[cfg(target_arch = "nvptx64")]
+impl<const W: usize, const H: usize, T: SharedMemory> Patch<W, H, T> {
+    /// Makes the patch centred on (base_col, base_row) of a tile of pixels, whose rows are tile_width pixels apart.
+    ///
+    /// # Safety
+    ///
+    /// `shared` must point to a tile, in shared memory on the device and in ordinary memory on the host, that stays
+    /// valid for as long as the patch. It must be initialized for every pixel the patch reaches: columns
+    /// `base_col - W / 2..=base_col + W / 2` and rows `base_row - H / 2..=base_row + H / 2`. Those columns must lie
+    /// within `0..tile_width` and those rows within the tile.
     pub unsafe fn new(
         shared: *const T,
-        block_width: usize,
+        tile_width: usize,
         base_col: usize,
         base_row: usize,
     ) -> Self {
         Self {
             shared,
-            block_width,
+            tile_width,
             base_col,
             base_row,
         }
     }
 
+    // The number of pixels on each side of the centre, horizontally and vertically
+    pub const fn radius() -> (usize, usize) {
+        (W / 2, H / 2)
+    }
+
+    // The pixel at (col, row) from the top left corner of the patch
     pub fn get(&self, col: usize, row: usize) -> T {
-        assert!(col < N);
-        assert!(row < N);
-        let offset = self.base_col + col - N / 2 + (self.base_row + row - N / 2) * self.block_width;
+        assert!(col < W);
+        assert!(row < H);
+        let offset = self.base_col + col - W / 2 + (self.base_row + row - H / 2) * self.tile_width;
         unsafe { T::load(self.shared.add(offset)) }
     }
+
+    // The pixel at (dx, dy) from the centre of the patch
+    pub fn at(&self, dx: isize, dy: isize) -> T {
+        let (radius_x, radius_y) = Self::radius();
+        assert!(-(radius_x as isize) <= dx && dx <= radius_x as isize);
+        assert!(-(radius_y as isize) <= dy && dy <= radius_y as isize);
+        self.get(
+            (dx + radius_x as isize) as usize,
+            (dy + radius_y as isize) as usize,
+        )
+    }
+
+    // The pixel the patch is the neighbourhood of
+    pub fn center(&self) -> T {
+        self.at(0, 0)
+    }
+
+    // Every pixel of the patch with its offset from the centre, row by row
+    pub fn iter(&self) -> PatchIter<'_, W, H, T> {
+        PatchIter {
+            patch: self,
+            col: 0,
+            row: 0,
+        }
+    }
+}
+
+pub struct PatchIter<'a, const W: usize, const H: usize, T: SharedMemory> {
+    patch: &'a Patch<W, H, T>,
+    col: usize,
+    row: usize,
+}
+
+impl<'a, const W: usize, const H: usize, T: SharedMemory> Iterator for PatchIter<'a, W, H, T> {
+    type Item = (isize, isize, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row == H {
+            return None;
+        }
+
+        let (radius_x, radius_y) = Patch::<W, H, T>::radius();
+        let item = (
+            self.col as isize - radius_x as isize,
+            self.row as isize - radius_y as isize,
+            self.patch.get(self.col, self.row),
+        );
+
+        self.col += 1;
+        if self.col == W {
+            self.col = 0;
+            self.row += 1;
+        }
+        Some(item)
+    }
+}
+
+impl<'a, const W: usize, const H: usize, T: SharedMemory> IntoIterator for &'a Patch<W, H, T> {
+    type Item = (isize, isize, T);
+    type IntoIter = PatchIter<'a, W, H, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
@@ -1,10 +1,21 @@
-use crate::Rgb;
+use crate::{CbCr, Hsv, Lab, Luma, Rgb, Rgba, YCbCr};
 
 pub trait SharedMemory
 where
     Self: Sized,
 {
+    /// Reads the value at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and aligned for `Self`. On the device, it must point into shared memory.
     unsafe fn load(ptr: *const Self) -> Self;
+
+    /// Writes the value to `ptr`, field by field for structs, so padding bytes are left as they are.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes and aligned for `Self`. On the device, it must point into shared memory.
     unsafe fn store(&self, ptr: *mut Self);
 }
 
@@ -23,14 +34,41 @@ impl SharedMemory for u8 {
     }
 }
 
+// on the host there is no shared memory, so patches are emulated with ordinary memory
 #[cfg(not(target_arch = "nvptx64"))]
 impl SharedMemory for u8 {
-    unsafe fn load(_ptr: *const Self) -> Self {
-        unimplemented!()
+    unsafe fn load(ptr: *const Self) -> Self {
+        ptr.read()
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        ptr.write(*self)
+    }
+}
+
+#[cfg(target_arch = "nvptx64")]
+impl SharedMemory for u16 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as u16;
+        let val;
+        core::arch::asm!("ld.shared.u16 {}, [{}];", out(reg16) val, in(reg16) ptr);
+        val
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as u16;
+        core::arch::asm!("st.shared.u16 [{}], {};", in(reg16) ptr, in(reg16) *self);
+    }
+}
+
+#[cfg(not(target_arch = "nvptx64"))]
+impl SharedMemory for u16 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        ptr.read()
     }
 
-    unsafe fn store(&self, _ptr: *mut Self) {
-        unimplemented!()
+    unsafe fn store(&self, ptr: *mut Self) {
+        ptr.write(*self)
     }
 }
 
@@ -51,12 +89,90 @@ impl SharedMemory for f32 {
 
 #[cfg(not(target_arch = "nvptx64"))]
 impl SharedMemory for f32 {
-    unsafe fn load(_ptr: *const Self) -> Self {
-        unimplemented!()
+    unsafe fn load(ptr: *const Self) -> Self {
+        ptr.read()
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        ptr.write(*self)
+    }
+}
+
+#[cfg(target_arch = "nvptx64")]
+impl SharedMemory for i16 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as u16;
+        let val;
+        core::arch::asm!("ld.shared.s16 {}, [{}];", out(reg16) val, in(reg16) ptr);
+        val
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as u16;
+        core::arch::asm!("st.shared.s16 [{}], {};", in(reg16) ptr, in(reg16) *self);
+    }
+}
+
+#[cfg(not(target_arch = "nvptx64"))]
+impl SharedMemory for i16 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        ptr.read()
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        ptr.write(*self)
+    }
+}
+
+#[cfg(target_arch = "nvptx64")]
+impl SharedMemory for u32 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as u16;
+        let val;
+        core::arch::asm!("ld.shared.u32 {}, [{}];", out(reg32) val, in(reg16) ptr);
+        val
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as u16;
+        core::arch::asm!("st.shared.u32 [{}], {};", in(reg16) ptr, in(reg32) *self);
+    }
+}
+
+#[cfg(not(target_arch = "nvptx64"))]
+impl SharedMemory for u32 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        ptr.read()
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        ptr.write(*self)
+    }
+}
+
+#[cfg(target_arch = "nvptx64")]
+impl SharedMemory for i32 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as u16;
+        let val;
+        core::arch::asm!("ld.shared.s32 {}, [{}];", out(reg32) val, in(reg16) ptr);
+        val
     }
 
-    unsafe fn store(&self, _ptr: *mut Self) {
-        unimplemented!()
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as u16;
+        core::arch::asm!("st.shared.s32 [{}], {};", in(reg16) ptr, in(reg32) *self);
+    }
+}
+
+#[cfg(not(target_arch = "nvptx64"))]
+impl SharedMemory for i32 {
+    unsafe fn load(ptr: *const Self) -> Self {
+        ptr.read()
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        ptr.write(*self)
     }
 }
 
@@ -77,3 +193,105 @@ impl<T: SharedMemory> SharedMemory for Rgb<T> {
         self.b.store(ptr.add(2));
     }
 }
+
+impl<T: SharedMemory> SharedMemory for Rgba<T> {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as *const T;
+        Self {
+            r: T::load(ptr),
+            g: T::load(ptr.add(1)),
+            b: T::load(ptr.add(2)),
+            a: T::load(ptr.add(3)),
+        }
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as *mut T;
+        self.r.store(ptr);
+        self.g.store(ptr.add(1));
+        self.b.store(ptr.add(2));
+        self.a.store(ptr.add(3));
+    }
+}
+
+impl<T: SharedMemory> SharedMemory for Luma<T> {
+    unsafe fn load(ptr: *const Self) -> Self {
+        Self {
+            l: T::load(ptr as *const T),
+        }
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        self.l.store(ptr as *mut T);
+    }
+}
+
+impl<T: SharedMemory> SharedMemory for Hsv<T> {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as *const T;
+        Self {
+            h: T::load(ptr),
+            s: T::load(ptr.add(1)),
+            v: T::load(ptr.add(2)),
+        }
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as *mut T;
+        self.h.store(ptr);
+        self.s.store(ptr.add(1));
+        self.v.store(ptr.add(2));
+    }
+}
+
+impl<T: SharedMemory> SharedMemory for YCbCr<T> {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as *const T;
+        Self {
+            y: T::load(ptr),
+            cb: T::load(ptr.add(1)),
+            cr: T::load(ptr.add(2)),
+        }
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as *mut T;
+        self.y.store(ptr);
+        self.cb.store(ptr.add(1));
+        self.cr.store(ptr.add(2));
+    }
+}
+
+impl<T: SharedMemory> SharedMemory for Lab<T> {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as *const T;
+        Self {
+            l: T::load(ptr),
+            a: T::load(ptr.add(1)),
+            b: T::load(ptr.add(2)),
+        }
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as *mut T;
+        self.l.store(ptr);
+        self.a.store(ptr.add(1));
+        self.b.store(ptr.add(2));
+    }
+}
+
+impl<T: SharedMemory> SharedMemory for CbCr<T> {
+    unsafe fn load(ptr: *const Self) -> Self {
+        let ptr = ptr as *const T;
+        Self {
+            cb: T::load(ptr),
+            cr: T::load(ptr.add(1)),
+        }
+    }
+
+    unsafe fn store(&self, ptr: *mut Self) {
+        let ptr = ptr as *mut T;
+        self.cb.store(ptr);
+        self.cr.store(ptr.add(1));
+    }
+}
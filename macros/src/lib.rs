@@ -2,7 +2,10 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::{quote, ToTokens};
-use syn::{parse_macro_input, parse_quote, Ident, ItemFn, TypePath};
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Expr, ExprLit, Fields, GenericArgument,
+    Ident, ItemFn, Lit, PathArguments, Type, TypePath,
+};
 use syn_quote_utils::{extract_inputs, extract_output};
 
 #[proc_macro_attribute]
@@ -30,6 +33,10 @@ pub fn map_patch_kernel(_args: TokenStream, item: TokenStream) -> TokenStream {
     let a = extract_inputs(&f)[0].1.clone();
     let b = extract_output(&f).clone();
 
+    if let Err(error) = check_patch(&a) {
+        return error.to_compile_error().into();
+    }
+
     let name = f.sig.ident.clone();
     let function = f.into_token_stream().to_string();
     let src = function.as_str();
@@ -41,6 +48,42 @@ pub fn map_patch_kernel(_args: TokenStream, item: TokenStream) -> TokenStream {
     .into()
 }
 
+// The patch of a map_patch kernel must be a Patch<W, H, T>. Sizes given as literals are checked to be odd here, while
+// other sizes are checked when the kernel is added to the graph.
+fn check_patch(type_path: &TypePath) -> syn::Result<()> {
+    let error = || {
+        syn::Error::new_spanned(
+            type_path,
+            "the input of a map_patch kernel must be an interface::Patch<W, H, T>",
+        )
+    };
+
+    let segment = type_path.path.segments.last().ok_or_else(error)?;
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return Err(error());
+    };
+    if segment.ident != "Patch" || arguments.args.len() != 3 {
+        return Err(error());
+    }
+
+    for argument in arguments.args.iter().take(2) {
+        if let GenericArgument::Const(Expr::Lit(ExprLit {
+            lit: Lit::Int(size),
+            ..
+        })) = argument
+        {
+            if size.base10_parse::<usize>()? % 2 == 0 {
+                return Err(syn::Error::new_spanned(
+                    size,
+                    "the width and height of a patch must be odd, so the pixel is at its centre",
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn is_valid_map_image_kernel_input(ident: &Ident, type_path: &TypePath) -> bool {
     let usize_type_path: TypePath = parse_quote! {usize};
     &usize_type_path == type_path && ["col", "row"].contains(&ident.to_string().as_str())
@@ -70,3 +113,134 @@ pub fn map_image_kernel(_args: TokenStream, item: TokenStream) -> TokenStream {
     }
     .into()
 }
+
+// The scalars pixel structs can be made of, which are the ones with shared memory access on the device
+const PIXEL_FIELD_TYPES: [&str; 6] = ["u8", "u16", "i16", "u32", "i32", "f32"];
+
+/// Implements Pixel for a `#[repr(C)]` struct with named fields of the scalars u8, u16, i16, u32, i32 and f32.
+///
+/// The struct itself is not part of the generated kernels. A copy of its definition is emitted into every kernel using
+/// it, with the fields but without the impl blocks and other attributes, so kernels must refer to it by its bare name
+/// and cannot call its methods or use traits implemented for it.
+#[proc_macro_derive(Pixel)]
+pub fn derive_pixel(item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as DeriveInput);
+    match pixel(&item) {
+        Ok(tokens) => tokens,
+        Err(error) => error.to_compile_error(),
+    }
+    .into()
+}
+
+fn pixel(item: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &item.ident;
+
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.generics,
+            "pixel structs cannot be generic",
+        ));
+    }
+
+    let mut repr_c = false;
+    for attr in item
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        attr.parse_nested_meta(|meta| {
+            repr_c |= meta.path.is_ident("C");
+            Ok(())
+        })?;
+    }
+    if !repr_c {
+        return Err(syn::Error::new_spanned(
+            name,
+            "pixel structs must be #[repr(C)], so their layout is the same on the host and the device",
+        ));
+    }
+
+    let Data::Struct(data) = &item.data else {
+        return Err(syn::Error::new_spanned(name, "pixels must be structs"));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &data.fields,
+            "pixel structs must have named fields",
+        ));
+    };
+
+    let mut field_names = Vec::new();
+    let mut field_types = Vec::new();
+    for field in fields.named.iter() {
+        let supported = match &field.ty {
+            Type::Path(type_path) => PIXEL_FIELD_TYPES
+                .iter()
+                .any(|scalar| type_path.path.is_ident(scalar)),
+            _ => false,
+        };
+        if !supported {
+            return Err(syn::Error::new_spanned(
+                &field.ty,
+                format!(
+                    "the fields of pixel structs must be one of {}",
+                    PIXEL_FIELD_TYPES.join(", ")
+                ),
+            ));
+        }
+        field_names.push(field.ident.clone().unwrap());
+        field_types.push(field.ty.clone());
+    }
+
+    // the generated kernels are compiled with the 2015 edition, where interface is an extern crate at their root
+    let device_shared_memory = shared_memory(name, &field_names, quote! {interface}, quote! {core});
+    let device_definition = quote! {
+        #[repr(C)]
+        #[derive(Clone, Copy, Default)]
+        struct #name {
+            #(#field_names: #field_types),*
+        }
+
+        #device_shared_memory
+    }
+    .to_string();
+
+    let shared_memory = shared_memory(name, &field_names, quote! {::interface}, quote! {::core});
+    let name_str = name.to_string();
+
+    Ok(quote! {
+        #shared_memory
+
+        unsafe impl ::cuda_fusion::Pixel for #name {
+            fn ty() -> ::cuda_fusion::PixelType {
+                ::cuda_fusion::PixelType::Custom(::cuda_fusion::CustomPixelType::new(
+                    #name_str,
+                    #device_definition,
+                    ::core::alloc::Layout::new::<Self>(),
+                ))
+            }
+        }
+    })
+}
+
+// Loads and stores the struct field by field, since shared memory is only accessed with scalar instructions
+fn shared_memory(
+    name: &Ident,
+    field_names: &[Ident],
+    interface: proc_macro2::TokenStream,
+    core: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        impl #interface::SharedMemory for #name {
+            unsafe fn load(ptr: *const Self) -> Self {
+                Self {
+                    #(#field_names: #interface::SharedMemory::load(#core::ptr::addr_of!((*ptr).#field_names))),*
+                }
+            }
+
+            unsafe fn store(&self, ptr: *mut Self) {
+                #(#interface::SharedMemory::store(&self.#field_names, #core::ptr::addr_of_mut!((*ptr).#field_names));)*
+            }
+        }
+    }
+}
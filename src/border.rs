@@ -0,0 +1,101 @@
+use std::mem::MaybeUninit;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::pixel::{Pixel, PixelType};
+
+// How the pixels outside of an image are made up
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Border<P> {
+    Constant(P),
+    // The nearest edge pixel
+    Replicate,
+    // The image mirrored at its edge pixels, which are not repeated
+    Reflect,
+    // The image repeated
+    Wrap,
+}
+
+impl<P: Pixel> Border<P> {
+    // The constant is kept as its bytes, so the graph need not be generic over pixel types. Padding bytes are zero.
+    pub(crate) fn into_bytes(self) -> Border<Vec<u8>> {
+        match self {
+            Border::Constant(pixel) => {
+                let size = std::mem::size_of::<P>();
+                let mut bytes = MaybeUninit::<P>::zeroed();
+                // Pixels are #[repr(C)] structs of scalars, which are stored field by field. The padding between the
+                // fields is never written, so it stays zeroed and every byte is initialized.
+                let bytes = unsafe {
+                    pixel.store(bytes.as_mut_ptr());
+                    std::slice::from_raw_parts(bytes.as_ptr() as *const u8, size)
+                };
+                Border::Constant(bytes.to_vec())
+            }
+            Border::Replicate => Border::Replicate,
+            Border::Reflect => Border::Reflect,
+            Border::Wrap => Border::Wrap,
+        }
+    }
+}
+
+impl Border<Vec<u8>> {
+    // Reads the pixel at the signed position with `read`, a `Fn(usize, usize) -> Option<P>` reading inside the image
+    pub(crate) fn read(
+        &self,
+        pixel_type: PixelType,
+        read: &syn::Ident,
+        col: TokenStream,
+        row: TokenStream,
+        width: TokenStream,
+        height: TokenStream,
+    ) -> TokenStream {
+        let (mode, outside) = match self {
+            Border::Constant(bytes) => {
+                let constant = pixel_type.constant(bytes);
+                (quote! {Constant}, quote! {Some(#constant)})
+            }
+            Border::Replicate => (quote! {Replicate}, quote! {None}),
+            Border::Reflect => (quote! {Reflect}, quote! {None}),
+            Border::Wrap => (quote! {Wrap}, quote! {None}),
+        };
+
+        quote! {
+            match (
+                interface::BorderMode::#mode.position(#col, #width),
+                interface::BorderMode::#mode.position(#row, #height),
+            ) {
+                (Some(col), Some(row)) => #read(col, row),
+                _ => #outside,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use interface::Rgb;
+
+    use super::Border;
+
+    #[test]
+    fn constant_bytes() {
+        let border = Border::Constant(Rgb::<u16> {
+            r: 1,
+            g: 0x0203,
+            b: 0xffff,
+        });
+        let mut expected = Vec::new();
+        for channel in [1u16, 0x0203, 0xffff] {
+            expected.extend(channel.to_ne_bytes());
+        }
+        assert_eq!(border.into_bytes(), Border::Constant(expected));
+    }
+
+    #[test]
+    fn modes_without_constant() {
+        assert_eq!(Border::<Rgb<u8>>::Replicate.into_bytes(), Border::Replicate);
+        assert_eq!(Border::<Rgb<u8>>::Reflect.into_bytes(), Border::Reflect);
+        assert_eq!(Border::<Rgb<u8>>::Wrap.into_bytes(), Border::Wrap);
+    }
+}
@@ -0,0 +1,340 @@
+use std::{alloc::Layout, cell::UnsafeCell, collections::HashMap, rc::Rc};
+
+use image::DynamicImage;
+use interface::{CbCr, Luma, Rgb, Rgba};
+use itertools::Itertools;
+
+use crate::{
+    error::InputError,
+    pixel::{ImagePixel, PixelType},
+    Error, Result,
+};
+
+// An input read by a compiled transformation
+#[derive(Clone, PartialEq, Debug)]
+pub struct RequiredInput {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub pixel_type: PixelType,
+}
+
+#[derive(Clone)]
+pub struct Buffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixel_type: PixelType,
+    pub inner: Rc<Box<UnsafeCell<[u8]>>>,
+}
+
+type ImageBuffer<P> = image::ImageBuffer<
+    <P as ImagePixel>::ImageCratePixel,
+    Vec<<<P as ImagePixel>::ImageCratePixel as image::Pixel>::Subpixel>,
+>;
+
+impl Buffer {
+    // Allocates a zeroed, tightly packed host buffer aligned for the pixel type
+    pub fn new(width: usize, height: usize, pixel_type: PixelType) -> Self {
+        let pixel_layout = pixel_type.layout();
+        let size = width * height * pixel_layout.size();
+        let layout = Layout::from_size_align(size, pixel_layout.align()).unwrap();
+        let ptr = if size == 0 {
+            pixel_layout.align() as *mut u8
+        } else {
+            unsafe { std::alloc::alloc_zeroed(layout) }
+        };
+        let inner = unsafe {
+            Rc::new(Box::from_raw(
+                std::ptr::slice_from_raw_parts_mut(ptr, size) as *mut UnsafeCell<[u8]>
+            ))
+        };
+
+        Self {
+            width,
+            height,
+            pixel_type,
+            inner,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.inner.get() as *mut u8
+    }
+
+    pub fn check(&self, name: &str, image: &DynamicImage) -> std::result::Result<(), InputError> {
+        if self.width == image.width() as usize
+            && self.height == image.height() as usize
+            && self.pixel_type.color_type() == image.color()
+        {
+            Ok(())
+        } else {
+            Err(InputError::Mismatched {
+                name: name.to_string(),
+                expected: (self.width, self.height, self.pixel_type),
+                found: (
+                    image.width() as usize,
+                    image.height() as usize,
+                    image.color(),
+                ),
+            })
+        }
+    }
+
+    pub fn required_input(&self, name: &str) -> RequiredInput {
+        RequiredInput {
+            name: name.to_string(),
+            width: self.width,
+            height: self.height,
+            pixel_type: self.pixel_type,
+        }
+    }
+
+    pub unsafe fn copy_from_dynamic_image(&mut self, image: &DynamicImage) {
+        assert_eq!(self.height, image.height() as usize);
+        assert_eq!(self.width, image.width() as usize);
+
+        fn copy<P: ImagePixel>(src: &ImageBuffer<P>, dst: &mut Buffer) {
+            assert_eq!(dst.pixel_type, P::ty());
+            let dst_slice = unsafe {
+                std::slice::from_raw_parts_mut(dst.inner.get() as *mut P, dst.height * dst.width)
+            };
+
+            for (src_px, dst_px) in std::iter::zip(src.pixels(), dst_slice) {
+                *dst_px = P::from_image_crate_pixel(*src_px);
+            }
+        }
+
+        match self.pixel_type {
+            PixelType::RgbU8 => copy::<Rgb<u8>>(image.as_rgb8().unwrap(), self),
+            PixelType::RgbU16 => copy::<Rgb<u16>>(image.as_rgb16().unwrap(), self),
+            PixelType::RgbF32 => copy::<Rgb<f32>>(image.as_rgb32f().unwrap(), self),
+            PixelType::RgbaU8 => copy::<Rgba<u8>>(image.as_rgba8().unwrap(), self),
+            PixelType::RgbaF32 => copy::<Rgba<f32>>(image.as_rgba32f().unwrap(), self),
+            PixelType::LumaU8 => copy::<Luma<u8>>(image.as_luma8().unwrap(), self),
+            PixelType::LumaU16 => copy::<Luma<u16>>(image.as_luma16().unwrap(), self),
+            PixelType::LumaF32 => copy::<Luma<f32>>(&image.to_luma32f(), self),
+            PixelType::CbCrU8 => copy::<CbCr<u8>>(image.as_luma_alpha8().unwrap(), self),
+            PixelType::HsvF32 | PixelType::YCbCrF32 | PixelType::LabF32 | PixelType::Custom(_) => {
+                unreachable!("inputs are made of image pixels")
+            }
+        };
+    }
+
+    pub unsafe fn to_dynamic_image(&self) -> DynamicImage {
+        fn to_image_buffer<P: ImagePixel>(buffer: &Buffer) -> ImageBuffer<P> {
+            assert_eq!(buffer.pixel_type, P::ty());
+            let pixels = unsafe {
+                std::slice::from_raw_parts(
+                    buffer.inner.get() as *const P,
+                    buffer.height * buffer.width,
+                )
+            };
+
+            ImageBuffer::<P>::from_fn(buffer.width as u32, buffer.height as u32, |x, y| {
+                pixels[x as usize + y as usize * buffer.width].into_image_crate_pixel()
+            })
+        }
+
+        match self.pixel_type {
+            PixelType::RgbU8 => DynamicImage::ImageRgb8(to_image_buffer::<Rgb<u8>>(self)),
+            PixelType::RgbU16 => DynamicImage::ImageRgb16(to_image_buffer::<Rgb<u16>>(self)),
+            PixelType::RgbF32 => DynamicImage::ImageRgb32F(to_image_buffer::<Rgb<f32>>(self)),
+            PixelType::RgbaU8 => DynamicImage::ImageRgba8(to_image_buffer::<Rgba<u8>>(self)),
+            PixelType::RgbaF32 => DynamicImage::ImageRgba32F(to_image_buffer::<Rgba<f32>>(self)),
+            PixelType::LumaU8 => DynamicImage::ImageLuma8(to_image_buffer::<Luma<u8>>(self)),
+            PixelType::LumaU16 => DynamicImage::ImageLuma16(to_image_buffer::<Luma<u16>>(self)),
+            PixelType::LumaF32 => DynamicImage::from(to_image_buffer::<Luma<f32>>(self)),
+            PixelType::CbCrU8 => DynamicImage::ImageLumaA8(to_image_buffer::<CbCr<u8>>(self)),
+            PixelType::HsvF32 | PixelType::YCbCrF32 | PixelType::LabF32 | PixelType::Custom(_) => {
+                unreachable!("outputs are made of image pixels")
+            }
+        }
+    }
+}
+
+// Checks the images given to a call against the input buffers, reporting every missing, unexpected and mismatched input
+pub fn check_inputs(
+    input_buffers: &HashMap<String, Buffer>,
+    images: &HashMap<String, DynamicImage>,
+) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for (name, buffer) in input_buffers.iter().sorted_by_key(|(name, _buffer)| *name) {
+        match images.get(name) {
+            Some(image) => errors.extend(buffer.check(name, image).err()),
+            None => errors.push(InputError::Missing(name.clone())),
+        }
+    }
+
+    for name in images.keys().sorted() {
+        if !input_buffers.contains_key(name) {
+            errors.push(InputError::Unexpected(name.clone()));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Inputs(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::{ColorType, DynamicImage, GrayImage, ImageBuffer, Luma, Rgb};
+
+    use super::{check_inputs, Buffer};
+    use crate::{error::InputError, pixel::PixelType, Error};
+
+    fn buffers(names: &[&str]) -> HashMap<String, Buffer> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), Buffer::new(4, 3, PixelType::RgbU8)))
+            .collect()
+    }
+
+    fn images(images: Vec<(&str, DynamicImage)>) -> HashMap<String, DynamicImage> {
+        images
+            .into_iter()
+            .map(|(name, image)| (name.to_string(), image))
+            .collect()
+    }
+
+    // The input errors of the call, panicking if it fails for another reason
+    fn input_errors(
+        input_buffers: &HashMap<String, Buffer>,
+        images: &HashMap<String, DynamicImage>,
+    ) -> Vec<InputError> {
+        match check_inputs(input_buffers, images) {
+            Ok(()) => Vec::new(),
+            Err(Error::Inputs(errors)) => errors,
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn matching_inputs() {
+        let images = images(vec![("a", DynamicImage::new_rgb8(4, 3))]);
+        assert!(input_errors(&buffers(&["a"]), &images).is_empty());
+    }
+
+    #[test]
+    fn missing_input() {
+        let images = images(vec![("a", DynamicImage::new_rgb8(4, 3))]);
+        let errors = input_errors(&buffers(&["a", "b"]), &images);
+        assert!(matches!(&errors[..], [InputError::Missing(name)] if name == "b"));
+    }
+
+    #[test]
+    fn unexpected_input() {
+        let images = images(vec![
+            ("a", DynamicImage::new_rgb8(4, 3)),
+            ("c", DynamicImage::new_rgb8(4, 3)),
+        ]);
+        let errors = input_errors(&buffers(&["a"]), &images);
+        assert!(matches!(&errors[..], [InputError::Unexpected(name)] if name == "c"));
+    }
+
+    #[test]
+    fn mismatched_size() {
+        let images = images(vec![("a", DynamicImage::new_rgb8(3, 4))]);
+        let errors = input_errors(&buffers(&["a"]), &images);
+        assert!(matches!(
+            &errors[..],
+            [InputError::Mismatched {
+                name,
+                expected: (4, 3, PixelType::RgbU8),
+                found: (3, 4, ColorType::Rgb8),
+            }] if name == "a"
+        ));
+    }
+
+    #[test]
+    fn mismatched_color_type() {
+        let images = images(vec![("a", DynamicImage::new_luma8(4, 3))]);
+        let errors = input_errors(&buffers(&["a"]), &images);
+        assert!(matches!(
+            &errors[..],
+            [InputError::Mismatched {
+                name,
+                expected: (4, 3, PixelType::RgbU8),
+                found: (4, 3, ColorType::L8),
+            }] if name == "a"
+        ));
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let images = images(vec![
+            ("b", DynamicImage::new_luma8(4, 3)),
+            ("d", DynamicImage::new_rgb8(4, 3)),
+        ]);
+        let errors = input_errors(&buffers(&["a", "b", "c"]), &images);
+        assert!(matches!(
+            &errors[..],
+            [
+                InputError::Missing(a),
+                InputError::Mismatched { name: b, .. },
+                InputError::Missing(c),
+                InputError::Unexpected(d),
+            ] if a == "a" && b == "b" && c == "c" && d == "d"
+        ));
+    }
+
+    // Copies the image into a buffer of the pixel type and back
+    fn round_trip(image: &DynamicImage, pixel_type: PixelType) -> (Buffer, DynamicImage) {
+        let mut buffer = Buffer::new(image.width() as usize, image.height() as usize, pixel_type);
+        unsafe { buffer.copy_from_dynamic_image(image) };
+        let image = unsafe { buffer.to_dynamic_image() };
+        (buffer, image)
+    }
+
+    #[test]
+    fn luma8_round_trips() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(4, 3, |col, row| {
+            Luma([(col * 60 + row) as u8])
+        }));
+        assert_eq!(round_trip(&image, PixelType::LumaU8).1, image);
+    }
+
+    #[test]
+    fn luma16_round_trips() {
+        let image = DynamicImage::ImageLuma16(ImageBuffer::from_fn(4, 3, |col, row| {
+            Luma([(col * 16000 + row) as u16])
+        }));
+        assert_eq!(round_trip(&image, PixelType::LumaU16).1, image);
+    }
+
+    #[test]
+    fn rgb16_round_trips() {
+        // every channel uses both bytes, so swapped or truncated bytes change the image
+        let image = DynamicImage::ImageRgb16(ImageBuffer::from_fn(4, 3, |col, row| {
+            Rgb([
+                0xfe00 + (col * 16 + row) as u16,
+                0x0100 * (col + 1) as u16 + row as u16,
+                u16::MAX - (col * 3 + row) as u16,
+            ])
+        }));
+        assert_eq!(round_trip(&image, PixelType::RgbU16).1, image);
+    }
+
+    #[test]
+    fn luma_f32_is_read_from_and_written_to_rgb32f() {
+        let image = DynamicImage::ImageRgb32F(ImageBuffer::from_fn(2, 1, |col, _row| match col {
+            0 => Rgb([0.25, 0.25, 0.25]),
+            _ => Rgb([1.0, 0.0, 0.0]),
+        }));
+        let (buffer, output) = round_trip(&image, PixelType::LumaF32);
+
+        // the input is reduced to its luma with the Rec. 709 weights of the image crate
+        let luma = unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const f32, 2) };
+        assert!((luma[0] - 0.25).abs() < 1e-6);
+        assert!((luma[1] - 0.2126).abs() < 1e-4);
+
+        let output = output.as_rgb32f().unwrap();
+        assert_eq!(output.get_pixel(0, 0).0, [luma[0]; 3]);
+        assert_eq!(output.get_pixel(1, 0).0, [luma[1]; 3]);
+    }
+}
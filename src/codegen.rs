@@ -1,283 +1,681 @@
+use std::collections::HashMap;
+
 use itertools::Itertools;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
 use syn::parse_quote;
-
-use crate::pixel::PixelType;
 use syn_quote_utils::extract_inputs;
 
-pub fn map_pixel(
-    ptr_in: usize,
-    ptr_out: usize,
-    width: usize,
-    height: usize,
-    pitch_in: usize,
-    pitch_out: usize,
-    pixel_type_in: PixelType,
-    pixel_type_out: PixelType,
-    f: &syn::ItemFn,
-    block_width: usize,
-    block_height: usize,
-) -> syn::ItemFn {
-    let inputs = extract_inputs(&f);
-    let (ident, _type_path) = inputs.first().unwrap();
-
-    let stmts = f.block.stmts.iter();
-
-    parse_quote! {
-        pub unsafe extern "ptx-kernel" fn kernel() {
-            let col = _block_idx_x() as usize * #block_width + _thread_idx_x() as usize;
-            let row = _block_idx_y() as usize * #block_height + _thread_idx_y() as usize;
-
-            let img_in: interface::Image<#pixel_type_in> = interface::Image::new(
-                #ptr_in as *mut u8, #width, #height, #pitch_in
-            );
-
-            let mut img_out: interface::Image<#pixel_type_out> = interface::Image::new(
-                #ptr_out as *mut u8, #width, #height, #pitch_out
-            );
+use crate::{
+    computational_dependency_graph::{Node, Operation},
+    pixel::{CustomPixelType, PixelType},
+};
+
+pub struct Kernel {
+    pub item_fn: syn::ItemFn,
+    // the values of the kernel parameters, in the order they are declared in
+    pub params: Vec<usize>,
+    // only the device launches kernels with blocks
+    #[cfg_attr(not(feature = "cuda"), allow(dead_code))]
+    pub geometry: LaunchGeometry,
+}
 
-            fn map_kernel(#ident: #pixel_type_in) -> #pixel_type_out {
-                #(#stmts)*
-            }
+// The blocks a kernel is launched with, where every thread computes one pixel of the output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LaunchGeometry {
+    pub block_width: usize,
+    pub block_height: usize,
+    pub grid_width: usize,
+    pub grid_height: usize,
+}
 
-            if let Some(px) = img_in.get(col, row) {
-                img_out[(col, row)] = map_kernel(px);
-            }
+impl LaunchGeometry {
+    // Covers an output of the given size with as few blocks as possible
+    pub fn new(width: usize, height: usize, block_width: usize, block_height: usize) -> Self {
+        Self {
+            block_width,
+            block_height,
+            grid_width: width.div_ceil(block_width),
+            grid_height: height.div_ceil(block_height),
         }
     }
 }
 
-pub fn map_patch(
-    ptr_in: usize,
+// Where a kernel is run
+#[cfg_attr(not(feature = "cuda"), allow(dead_code))]
+pub enum Target {
+    // A ptx kernel named `kernel`, launched with one thread per pixel
+    Device,
+    // An ordinary function with the given name, which computes the blocks one after the other and the pixels of each
+    // block one after the other. It runs the same code as the device, so its results are a reference for the device.
+    Host(syn::Ident),
+}
+
+// Generates the kernel computing the node, with every dependency that has no device pointer fused into it.
+//
+// Each node in the kernel becomes a closure `|col, row| -> Option<P>` returning its pixel at the position, or None if the
+// position is outside of it. A kernel containing a map_patch first loads the tile of its thread block into shared memory,
+// which is the block of pixels it computes surrounded by the halo its patches reach into.
+//
+// Device pointers, sizes and pitches are not part of the source, but returned as the values of the kernel parameters, so
+// graphs of the same structure compile to the same kernel whatever their image sizes.
+pub fn kernel(
+    node: &Node,
     ptr_out: usize,
-    width: usize,
-    height: usize,
-    pitch_in: usize,
-    pitch_out: usize,
-    pixel_type_in: PixelType,
-    pixel_type_out: PixelType,
-    f: &syn::ItemFn,
-    dimension: usize,
+    device_ptr: impl Fn(&Node) -> Option<usize>,
+    alignment: usize,
     block_width: usize,
     block_height: usize,
-) -> syn::ItemFn {
-    let inputs = extract_inputs(&f);
-    let (ident, _type_path) = inputs.first().unwrap();
-
-    let stmts = f.block.stmts.iter();
-
-    assert!(dimension % 2 == 1);
-    let padding = dimension / 2;
-
-    let shared_memory_declearation = format!(
-        ".shared .align {} .b8 SHARED[{}];",
-        pixel_type_in.layout().align(),
-        block_height * block_width * pixel_type_in.layout().size()
-    );
-
-    parse_quote! {
-        pub unsafe extern "ptx-kernel" fn kernel() {
-            let thread_col = _thread_idx_x() as usize;
-            let thread_row = _thread_idx_y() as usize;
-            let col = _block_idx_x() as usize * (#block_width - 2 * #padding) + thread_col - #padding;
-            let row = _block_idx_y() as usize * (#block_width - 2 * #padding) + thread_row - #padding;
-
-            let img_in: interface::Image<#pixel_type_in> = interface::Image::new(
-                #ptr_in as *mut u8, #width, #height, #pitch_in
-            );
+    target: Target,
+) -> Kernel {
+    let Node::Operation(operation) = node else {
+        panic!("inputs are copied to the device, not computed by kernels")
+    };
+
+    let mut builder = KernelBuilder {
+        device_ptr,
+        alignment,
+        block_width,
+        stmts: Vec::new(),
+        leaves: HashMap::new(),
+        patch: None,
+        params: Vec::new(),
+        custom_pixel_types: Vec::new(),
+    };
+    let ptr_out = builder.param("ptr_out", ptr_out);
+    let width = builder.param("width_out", node.width());
+    let height = builder.param("height_out", node.height());
+    let pitch_out = builder.param("pitch_out", node.pitch(alignment));
+    let root = builder.operation(operation);
+
+    let KernelBuilder {
+        device_ptr: _,
+        alignment: _,
+        block_width: _,
+        stmts,
+        leaves: _,
+        patch,
+        params,
+        custom_pixel_types,
+    } = builder;
+
+    let (param_idents, params): (Vec<_>, Vec<_>) = params.into_iter().unzip();
+    let pixel_type_out = node.pixel_type();
+    let definitions = custom_pixel_types
+        .iter()
+        .flat_map(CustomPixelType::definition)
+        .collect_vec();
+
+    let declare_out = quote! {
+        let mut img_out: interface::Image<#pixel_type_out> = interface::Image::new(
+            #ptr_out as *mut u8, #width, #height, #pitch_out
+        );
+    };
+    let store = quote! {
+        if col < #width && row < #height {
+            if let Some(px) = #root(col, row) {
+                img_out[(col, row)] = px;
+            }
+        }
+    };
 
-            let px = img_in.get(col, row).unwrap_or_default();
+    let item_fn = match (target, patch) {
+        (Target::Device, None) => {
+            parse_quote! {
+                pub unsafe extern "ptx-kernel" fn kernel(#(#param_idents: usize),*) {
+                    #(#definitions)*
 
-            use interface::SharedMemory;
-            core::arch::asm!(#shared_memory_declearation);
-            let shared: *mut #pixel_type_in;
-            core::arch::asm!("mov.u64 {}, SHARED;", out(reg64) shared);
+                    let col = _block_idx_x() as usize * #block_width + _thread_idx_x() as usize;
+                    let row = _block_idx_y() as usize * #block_height + _thread_idx_y() as usize;
 
-            let thread_i = thread_col + thread_row * #block_width;
-            px.store(shared.add(thread_i));
+                    #(#stmts)*
 
-            if thread_col < #padding || thread_col + #padding >= #block_width ||
-                thread_row < #padding || thread_row + #padding >= #block_height {
-                return;
+                    #declare_out
+                    #store
+                }
             }
+        }
 
-            _syncthreads();
+        (Target::Host(name), None) => {
+            parse_quote! {
+                pub unsafe fn #name(#(#param_idents: usize),*) {
+                    #(#definitions)*
 
-            let mut img_out: interface::Image<#pixel_type_out> = interface::Image::new(
-                #ptr_out as *mut u8, #width, #height, #pitch_out
-            );
+                    #(#stmts)*
 
-            let patch: interface::Patch<#dimension, #pixel_type_in> = interface::Patch::new(
-               shared, #block_width, thread_col, thread_row
-            );
-
-            fn map_kernel(#ident: interface::Patch<#dimension, #pixel_type_in>) -> #pixel_type_out {
-                #(#stmts)*
+                    #declare_out
+                    for row in 0..#height {
+                        for col in 0..#width {
+                            #store
+                        }
+                    }
+                }
             }
+        }
 
-            if let Some(px) = img_out.get_mut(col, row) {
-                *px = map_kernel(patch);
+        (
+            target,
+            Some(TiledPatch {
+                load,
+                patch_width,
+                patch_height,
+                pixel_type,
+            }),
+        ) => {
+            let padding_x = patch_width / 2;
+            let padding_y = patch_height / 2;
+            let tile_width = block_width + 2 * padding_x;
+            let tile_height = block_height + 2 * padding_y;
+
+            // loads the pixel tile_i of the tile, row by row
+            let load_tile = quote! {
+                let load_col = (block_col + tile_i % #tile_width) as isize - #padding_x as isize;
+                let load_row = (block_row + tile_i / #tile_width) as isize - #padding_y as isize;
+                let px = { #load }.unwrap_or_default();
+                px.store(shared.add(tile_i));
+            };
+
+            match target {
+                Target::Device => {
+                    let shared_memory_declearation = format!(
+                        ".shared .align {} .b8 SHARED[{}];",
+                        pixel_type.layout().align(),
+                        tile_width * tile_height * pixel_type.layout().size()
+                    );
+
+                    parse_quote! {
+                        pub unsafe extern "ptx-kernel" fn kernel(#(#param_idents: usize),*) {
+                            #(#definitions)*
+
+                            let thread_col = _thread_idx_x() as usize;
+                            let thread_row = _thread_idx_y() as usize;
+                            let block_col = _block_idx_x() as usize * #block_width;
+                            let block_row = _block_idx_y() as usize * #block_height;
+                            let col = block_col + thread_col;
+                            let row = block_row + thread_row;
+
+                            use interface::SharedMemory;
+                            core::arch::asm!(#shared_memory_declearation);
+                            let shared: *mut #pixel_type;
+                            core::arch::asm!("mov.u64 {}, SHARED;", out(reg64) shared);
+
+                            #(#stmts)*
+
+                            // the threads load the tile together, so neighbouring threads read neighbouring pixels, with
+                            // each thread loading every (block_width * block_height)th pixel until the halo is covered as well
+                            let mut tile_i = thread_col + thread_row * #block_width;
+                            while tile_i < #tile_width * #tile_height {
+                                #load_tile
+                                tile_i += #block_width * #block_height;
+                            }
+
+                            _syncthreads();
+
+                            #declare_out
+                            #store
+                        }
+                    }
+                }
+
+                Target::Host(name) => {
+                    parse_quote! {
+                        pub unsafe fn #name(#(#param_idents: usize),*) {
+                            #(#definitions)*
+
+                            use interface::SharedMemory;
+                            let mut tile = vec![<#pixel_type as Default>::default(); #tile_width * #tile_height];
+                            let shared: *mut #pixel_type = tile.as_mut_ptr();
+
+                            #declare_out
+                            for block_row in (0..#height).step_by(#block_height) {
+                                for block_col in (0..#width).step_by(#block_width) {
+                                    #(#stmts)*
+
+                                    for tile_i in 0..#tile_width * #tile_height {
+                                        #load_tile
+                                    }
+
+                                    for row in block_row..block_row + #block_height {
+                                        for col in block_col..block_col + #block_width {
+                                            #store
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
             }
         }
+    };
+
+    Kernel {
+        item_fn,
+        params,
+        geometry: LaunchGeometry::new(node.width(), node.height(), block_width, block_height),
     }
 }
 
-pub fn map_image(
-    ptr_in: usize,
-    ptr_out: usize,
-    width_in: usize,
-    width_out: usize,
-    height_in: usize,
-    height_out: usize,
-    pitch_in: usize,
-    pitch_out: usize,
-    pixel_type_in: PixelType,
-    pixel_type_out: PixelType,
-    f: &syn::ItemFn,
-    block_width: usize,
-    block_height: usize,
-) -> syn::ItemFn {
-    let stmts = f.block.stmts.iter();
+// The map_patch of a kernel, whose dependency is loaded into the shared memory tile
+struct TiledPatch {
+    // reads the pixel of the dependency at (col, row), or its border outside of it
+    load: TokenStream,
+    patch_width: usize,
+    patch_height: usize,
+    pixel_type: PixelType,
+}
 
-    let fn_args = f.sig.inputs.iter().skip(1).collect_vec();
+struct KernelBuilder<F> {
+    device_ptr: F,
+    alignment: usize,
+    block_width: usize,
+    stmts: Vec<TokenStream>,
+    // the closures reading materialized nodes, which may be read more than once by the same kernel
+    leaves: HashMap<*const Node, syn::Ident>,
+    patch: Option<TiledPatch>,
+    params: Vec<(syn::Ident, usize)>,
+    // the pixel structs the kernel refers to, whose definitions are emitted at the top of it
+    custom_pixel_types: Vec<CustomPixelType>,
+}
 
-    let mut input_iter = extract_inputs(&f).into_iter();
-    let (ident, _type_path) = input_iter.next().unwrap();
-    let meta_args = input_iter.map(|(ident, _type_path)| ident).collect_vec();
+impl<F: Fn(&Node) -> Option<usize>> KernelBuilder<F> {
+    // Defines the closure computing the node and returns its identifier
+    fn node(&mut self, node: &Node) -> syn::Ident {
+        let Some(ptr) = (self.device_ptr)(node) else {
+            let Node::Operation(operation) = node else {
+                panic!("inputs are always materialized")
+            };
+            return self.operation(operation);
+        };
 
-    parse_quote! {
-        pub unsafe extern "ptx-kernel" fn kernel() {
-            let col = _block_idx_x() as usize * #block_width + _thread_idx_x() as usize;
-            let row = _block_idx_y() as usize * #block_height + _thread_idx_y() as usize;
+        if let Some(ident) = self.leaves.get(&(node as *const _)) {
+            return ident.clone();
+        }
 
-            let img_in: interface::Image<#pixel_type_in> = interface::Image::new(
-                #ptr_in as *mut u8, #width_in, #height_in, #pitch_in
+        let ident = self.ident("node");
+        let ptr = self.param("ptr", ptr);
+        let width = self.param("width", node.width());
+        let height = self.param("height", node.height());
+        let pitch = self.param("pitch", node.pitch(self.alignment));
+        let pixel_type = node.pixel_type();
+        self.use_pixel_type(pixel_type);
+        let img = self.ident("img");
+
+        self.stmts.push(quote! {
+            let #img: interface::Image<#pixel_type> = interface::Image::new(
+                #ptr as *mut u8, #width, #height, #pitch
             );
+            let #ident = |col: usize, row: usize| -> Option<#pixel_type> { #img.get(col, row) };
+        });
 
-            let mut img_out: interface::Image<#pixel_type_out> = interface::Image::new(
-                #ptr_out as *mut u8, #width_out, #height_out, #pitch_out
-            );
+        self.leaves.insert(node, ident.clone());
+        ident
+    }
 
-            fn map_kernel(#ident: interface::Image<#pixel_type_in>, #(#fn_args),*) -> #pixel_type_out {
-                #(#stmts)*
+    fn operation(&mut self, operation: &Operation) -> syn::Ident {
+        let pixel_type_out = operation.pixel_type();
+        self.use_pixel_type(pixel_type_out);
+
+        let body = match operation {
+            Operation::MapPixel {
+                dependency,
+                f,
+                pixel_type: _,
+            } => {
+                let dependency_ident = self.node(dependency);
+                let pixel_type_in = dependency.pixel_type();
+                let inputs = extract_inputs(f);
+                let (ident, _type_path) = inputs.first().unwrap();
+                let stmts = f.block.stmts.iter();
+                let map_kernel = self.ident("map_kernel");
+
+                self.stmts.push(quote! {
+                    fn #map_kernel(#ident: #pixel_type_in) -> #pixel_type_out {
+                        #(#stmts)*
+                    }
+                });
+                quote! { #dependency_ident(col, row).map(#map_kernel) }
             }
 
-            if col < #width_out && row < #height_out {
-                img_out[(col, row)] = map_kernel(img_in, #(#meta_args),*);
+            Operation::MapPatch {
+                dependency,
+                f,
+                patch_width,
+                patch_height,
+                pixel_type: _,
+                border,
+            } => {
+                let dependency_ident = self.node(dependency);
+                let pixel_type_in = dependency.pixel_type();
+                let inputs = extract_inputs(f);
+                let (ident, _type_path) = inputs.first().unwrap();
+                let stmts = f.block.stmts.iter();
+                let map_kernel = self.ident("map_kernel");
+                let tile_width = self.block_width + patch_width - 1;
+
+                assert!(patch_width % 2 == 1 && patch_height % 2 == 1);
+                assert!(
+                    self.patch.is_none(),
+                    "a kernel can only contain one map_patch"
+                );
+                // the halo of the tile reaches outside of the image at its edges, where the border is read instead
+                let width_in = self.param("width", dependency.width());
+                let height_in = self.param("height", dependency.height());
+                let load = border.read(
+                    pixel_type_in,
+                    &dependency_ident,
+                    quote! {load_col},
+                    quote! {load_row},
+                    quote! {#width_in},
+                    quote! {#height_in},
+                );
+                self.patch = Some(TiledPatch {
+                    load,
+                    patch_width: *patch_width,
+                    patch_height: *patch_height,
+                    pixel_type: pixel_type_in,
+                });
+
+                self.stmts.push(quote! {
+                    fn #map_kernel(#ident: interface::Patch<#patch_width, #patch_height, #pixel_type_in>) -> #pixel_type_out {
+                        #(#stmts)*
+                    }
+                });
+                // the map_patch is only evaluated at the pixels of the block, which are offset in the tile by its halo
+                let padding_x = patch_width / 2;
+                let padding_y = patch_height / 2;
+                quote! {
+                    let patch: interface::Patch<#patch_width, #patch_height, #pixel_type_in> = interface::Patch::new(
+                        shared, #tile_width, col - block_col + #padding_x, row - block_row + #padding_y
+                    );
+                    Some(#map_kernel(patch))
+                }
             }
-        }
-    }
-}
 
-pub fn flip(
-    ptr_in: usize,
-    ptr_out: usize,
-    width: usize,
-    height: usize,
-    pitch: usize,
-    pixel_type: PixelType,
-    block_width: usize,
-    block_height: usize,
-) -> syn::ItemFn {
-    parse_quote! {
-        pub unsafe extern "ptx-kernel" fn kernel() {
-            let col = _block_idx_x() as usize * #block_width + _thread_idx_x() as usize;
-            let row = _block_idx_y() as usize * #block_height + _thread_idx_y() as usize;
-
-            let img_in: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_in as *mut u8, #width, #height, #pitch
-            );
+            Operation::MapImage {
+                dependency,
+                f,
+                width,
+                height,
+                pixel_type: _,
+            } => {
+                let ptr_in = (self.device_ptr)(dependency)
+                    .expect("the dependency of map_image is always materialized");
+                let ptr_in = self.param("ptr", ptr_in);
+                let width_in = self.param("width", dependency.width());
+                let height_in = self.param("height", dependency.height());
+                let pitch_in = self.param("pitch", dependency.pitch(self.alignment));
+                let width = self.param("width", *width);
+                let height = self.param("height", *height);
+                let pixel_type_in = dependency.pixel_type();
+                self.use_pixel_type(pixel_type_in);
+                let stmts = f.block.stmts.iter();
+                let fn_args = f.sig.inputs.iter().skip(1).collect_vec();
+
+                let mut input_iter = extract_inputs(f).into_iter();
+                let (ident, _type_path) = input_iter.next().unwrap();
+                let meta_args = input_iter.map(|(ident, _type_path)| ident).collect_vec();
+                let map_kernel = self.ident("map_kernel");
+
+                self.stmts.push(quote! {
+                    fn #map_kernel(#ident: interface::Image<#pixel_type_in>, #(#fn_args),*) -> #pixel_type_out {
+                        #(#stmts)*
+                    }
+                });
+                quote! {
+                    if col < #width && row < #height {
+                        let img_in: interface::Image<#pixel_type_in> = interface::Image::new(
+                            #ptr_in as *mut u8, #width_in, #height_in, #pitch_in
+                        );
+                        Some(#map_kernel(img_in, #(#meta_args),*))
+                    } else {
+                        None
+                    }
+                }
+            }
 
-            let mut img_out: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_out as *mut u8, #width, #height, #pitch
-            );
+            Operation::Flip { dependency } => {
+                let dependency_ident = self.node(dependency);
+                let width = self.param("width", dependency.width());
+                let height = self.param("height", dependency.height());
+                quote! {
+                    if col < #width && row < #height {
+                        #dependency_ident(#width - col - 1, #height - row - 1)
+                    } else {
+                        None
+                    }
+                }
+            }
 
-            if let Some(px) = img_in.get(col, row) {
-                img_out[(#width - col - 1, #height - row - 1)] = px;
+            Operation::Crop {
+                dependency,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let dependency_ident = self.node(dependency);
+                let x = self.param("x", *x);
+                let y = self.param("y", *y);
+                let width = self.param("width", *width);
+                let height = self.param("height", *height);
+                quote! {
+                    if col < #width && row < #height {
+                        #dependency_ident(col + #x, row + #y)
+                    } else {
+                        None
+                    }
+                }
             }
-        }
-    }
-}
 
-pub fn h_concat(
-    ptr_left: usize,
-    ptr_right: usize,
-    ptr_out: usize,
-    width_left: usize,
-    width_right: usize,
-    width_out: usize,
-    height: usize,
-    pitch_left: usize,
-    pitch_right: usize,
-    pitch_out: usize,
-    pixel_type: PixelType,
-    block_width: usize,
-    block_height: usize,
-) -> syn::ItemFn {
-    parse_quote! {
-        pub unsafe extern "ptx-kernel" fn kernel() {
-            let col = _block_idx_x() as usize * #block_width + _thread_idx_x() as usize;
-            let row = _block_idx_y() as usize * #block_height + _thread_idx_y() as usize;
-
-            let img_left: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_left as *mut u8, #width_left, #height, #pitch_left
-            );
+            Operation::Pad {
+                dependency,
+                left,
+                right: _,
+                top,
+                bottom: _,
+                border,
+            } => {
+                let dependency_ident = self.node(dependency);
+                let width = self.param("width", operation.width());
+                let height = self.param("height", operation.height());
+                let left = self.param("left", *left);
+                let top = self.param("top", *top);
+                let width_in = self.param("width", dependency.width());
+                let height_in = self.param("height", dependency.height());
+                let read = border.read(
+                    pixel_type_out,
+                    &dependency_ident,
+                    quote! {col as isize - #left as isize},
+                    quote! {row as isize - #top as isize},
+                    quote! {#width_in},
+                    quote! {#height_in},
+                );
+                quote! {
+                    if col < #width && row < #height {
+                        #read
+                    } else {
+                        None
+                    }
+                }
+            }
 
-            let img_right: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_right as *mut u8, #width_right, #height, #pitch_right
-            );
+            Operation::Resize {
+                dependency,
+                width,
+                height,
+                filter,
+            } => {
+                let dependency_ident = self.node(dependency);
+                let width = self.param("width", *width);
+                let height = self.param("height", *height);
+                let width_in = self.param("width", dependency.width());
+                let height_in = self.param("height", dependency.height());
+                let sample = filter.sample(
+                    pixel_type_out,
+                    &dependency_ident,
+                    quote! {#width_in},
+                    quote! {#height_in},
+                    quote! {#width},
+                    quote! {#height},
+                );
+                quote! {
+                    if col < #width && row < #height {
+                        #sample
+                    } else {
+                        None
+                    }
+                }
+            }
 
-            let mut img_out: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_out as *mut u8, #width_out, #height, #pitch_out
-            );
+            Operation::ConvertColourSpace {
+                dependency,
+                pixel_type: _,
+            } => {
+                let dependency_ident = self.node(dependency);
+                let pixel_type_in = dependency.pixel_type();
+                quote! {
+                    #dependency_ident(col, row)
+                        .map(<#pixel_type_out as interface::FromPixel<#pixel_type_in>>::from_pixel)
+                }
+            }
 
-            if col < #width_left {
-                if let Some(px) = img_left.get(col, row) {
-                    img_out[(col, row)] = px;
+            // the chroma of a pixel is shared by the 2x2 pixels around it
+            Operation::Nv12ToRgb {
+                dependency_y,
+                dependency_uv,
+                pixel_type: _,
+            } => {
+                let y = self.node(dependency_y);
+                let uv = self.node(dependency_uv);
+                quote! {
+                    match (#y(col, row), #uv(col / 2, row / 2)) {
+                        (Some(y), Some(uv)) => Some(
+                            <#pixel_type_out as interface::FromPixel<interface::YCbCr<f32>>>::from_pixel(
+                                interface::YCbCr::from_video_range(y.l, uv.cb, uv.cr)
+                            )
+                        ),
+                        _ => None,
+                    }
                 }
-            } else if let Some(px) = img_right.get(col - #width_left, row) {
-                img_out[(col, row)] = px;
-            };
+            }
+
+            Operation::I420ToRgb {
+                dependency_y,
+                dependency_u,
+                dependency_v,
+                pixel_type: _,
+            } => {
+                let y = self.node(dependency_y);
+                let u = self.node(dependency_u);
+                let v = self.node(dependency_v);
+                quote! {
+                    match (#y(col, row), #u(col / 2, row / 2), #v(col / 2, row / 2)) {
+                        (Some(y), Some(u), Some(v)) => Some(
+                            <#pixel_type_out as interface::FromPixel<interface::YCbCr<f32>>>::from_pixel(
+                                interface::YCbCr::from_video_range(y.l, u.l, v.l)
+                            )
+                        ),
+                        _ => None,
+                    }
+                }
+            }
+
+            Operation::HConcat {
+                dependency_left,
+                dependency_right,
+            } => {
+                let left = self.node(dependency_left);
+                let right = self.node(dependency_right);
+                let width_left = self.param("width", dependency_left.width());
+                quote! {
+                    if col < #width_left {
+                        #left(col, row)
+                    } else {
+                        #right(col - #width_left, row)
+                    }
+                }
+            }
+
+            Operation::VConcat {
+                dependency_top,
+                dependency_bottom,
+            } => {
+                let top = self.node(dependency_top);
+                let bottom = self.node(dependency_bottom);
+                let height_top = self.param("height", dependency_top.height());
+                quote! {
+                    if row < #height_top {
+                        #top(col, row)
+                    } else {
+                        #bottom(col, row - #height_top)
+                    }
+                }
+            }
+        };
+
+        let ident = self.ident("node");
+        self.stmts.push(quote! {
+            let #ident = |col: usize, row: usize| -> Option<#pixel_type_out> { #body };
+        });
+        ident
+    }
+
+    fn use_pixel_type(&mut self, pixel_type: PixelType) {
+        if let PixelType::Custom(custom) = pixel_type {
+            if !self.custom_pixel_types.contains(&custom) {
+                self.custom_pixel_types.push(custom);
+            }
         }
     }
+
+    fn ident(&self, prefix: &str) -> syn::Ident {
+        format_ident!("{}_{}", prefix, self.stmts.len())
+    }
+
+    // Adds a kernel parameter and returns its identifier
+    fn param(&mut self, prefix: &str, value: usize) -> syn::Ident {
+        let ident = format_ident!("{}_{}", prefix, self.params.len());
+        self.params.push((ident.clone(), value));
+        ident
+    }
 }
 
-pub fn v_concat(
-    ptr_top: usize,
-    ptr_bottom: usize,
-    ptr_out: usize,
-    width: usize,
-    height_top: usize,
-    height_bottom: usize,
-    height_out: usize,
-    pitch: usize,
-    pixel_type: PixelType,
-    block_width: usize,
-    block_height: usize,
-) -> syn::ItemFn {
-    parse_quote! {
-        pub unsafe extern "ptx-kernel" fn kernel() {
-            let col = _block_idx_x() as usize * #block_width + _thread_idx_x() as usize;
-            let row = _block_idx_y() as usize * #block_height + _thread_idx_y() as usize;
-
-            let img_top: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_top as *mut u8, #width, #height_top, #pitch
-            );
+#[cfg(test)]
+mod tests {
+    use super::LaunchGeometry;
+
+    fn grid(
+        width: usize,
+        height: usize,
+        block_width: usize,
+        block_height: usize,
+    ) -> (usize, usize) {
+        let geometry = LaunchGeometry::new(width, height, block_width, block_height);
+        assert_eq!(
+            (geometry.block_width, geometry.block_height),
+            (block_width, block_height)
+        );
+        (geometry.grid_width, geometry.grid_height)
+    }
 
-            let img_bottom: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_bottom as *mut u8, #width, #height_bottom, #pitch
-            );
+    #[test]
+    fn multiples_of_the_block_size() {
+        assert_eq!(grid(64, 48, 16, 16), (4, 3));
+        assert_eq!(grid(16, 16, 16, 16), (1, 1));
+    }
 
-            let mut img_out: interface::Image<#pixel_type> = interface::Image::new(
-                #ptr_out as *mut u8, #width, #height_out, #pitch
-            );
+    #[test]
+    fn partial_blocks_are_launched() {
+        assert_eq!(grid(65, 48, 16, 16), (5, 3));
+        assert_eq!(grid(64, 33, 16, 16), (4, 3));
+        assert_eq!(grid(1920, 1080, 32, 8), (60, 135));
+        assert_eq!(grid(1000, 750, 32, 32), (32, 24));
+    }
 
-            if row < #height_top {
-                if let Some(px) = img_top.get(col, row) {
-                    img_out[(col, row)] = px;
-                }
-            } else if let Some(px) = img_bottom.get(col, row - #height_top) {
-                img_out[(col, row)] = px;
-            };
-        }
+    #[test]
+    fn images_smaller_than_a_block() {
+        assert_eq!(grid(1, 1, 16, 16), (1, 1));
+        assert_eq!(grid(5, 3, 32, 8), (1, 1));
     }
 }
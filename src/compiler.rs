@@ -1,9 +1,29 @@
-use std::{fs, process};
+#[cfg(feature = "cuda")]
+use std::{
+    env,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+use std::{env::consts, fs, process, sync::OnceLock};
 
-const INTERFACE_RLIB: &'static [u8] =
-    include_bytes!(concat!(env!("OUT_DIR"), "/libinterface.rlib"));
+#[cfg(feature = "cuda")]
+use sha2::{Digest, Sha256};
 
-pub fn compile(item_fn: syn::ItemFn) -> Result<String, ()> {
+use crate::{Error, Result};
+
+const TOOLCHAIN: &str = "nightly-2022-10-13";
+#[cfg(feature = "cuda")]
+const TARGET_CPU: &str = "sm_75";
+
+#[cfg(feature = "cuda")]
+const INTERFACE_RLIB: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libinterface.rlib"));
+
+const HOST_INTERFACE_RLIB: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/host/libinterface.rlib"));
+
+#[cfg(feature = "cuda")]
+pub fn compile(item_fn: syn::ItemFn) -> Result<String> {
     let panic_handler = panic_handler();
     let file: syn::File = syn::parse_quote! {
         #![no_std]
@@ -12,6 +32,9 @@ pub fn compile(item_fn: syn::ItemFn) -> Result<String, ()> {
         extern crate interface;
 
         use core::arch::nvptx::*;
+        // kernels may use the conversion traits without naming them
+        #[allow(unused_imports)]
+        use interface::{Channel, FromPixel, IntoPixel};
 
         #[no_mangle]
         #item_fn
@@ -19,15 +42,25 @@ pub fn compile(item_fn: syn::ItemFn) -> Result<String, ()> {
         #panic_handler
     };
 
-    let dir = tempfile::tempdir().unwrap();
-    fs::write(dir.path().join("codegen.rs"), prettyplease::unparse(&file)).unwrap();
-    fs::write(dir.path().join("libinterface.rlib"), INTERFACE_RLIB).unwrap();
+    let source = prettyplease::unparse(&file);
+
+    let cached_path = cache_dir().map(|dir| dir.join(format!("{}.ptx", cache_key(&source))));
+    if let Some(ptx) = cached_path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    {
+        return Ok(ptx);
+    }
+
+    check_toolchain()?;
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("codegen.rs"), &source)?;
+    fs::write(dir.path().join("libinterface.rlib"), INTERFACE_RLIB)?;
 
     let output = process::Command::new("rustup")
-        .stderr(process::Stdio::inherit())
         .current_dir(&dir)
         .arg("run")
-        .arg("nightly-2022-10-13")
+        .arg(TOOLCHAIN)
         .arg("rustc")
         .arg("codegen.rs")
         .arg("--target")
@@ -39,18 +72,149 @@ pub fn compile(item_fn: syn::ItemFn) -> Result<String, ()> {
         .arg("-L")
         .arg(".")
         .arg("-C")
-        .arg("target-cpu=sm_75")
-        .output()
-        .unwrap();
+        .arg(format!("target-cpu={TARGET_CPU}"))
+        .output()?;
+
+    if output.status.success() {
+        let ptx = fs::read_to_string(dir.path().join("codegen.ptx"))?;
+        if let Some(path) = cached_path {
+            // the cache is only an optimisation, so failing to write to it is not an error
+            let _ = store(&path, &ptx);
+        }
+        Ok(ptx)
+    } else {
+        Err(Error::Compile {
+            source,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+// Compiles the items for the host into a dynamic library and loads it
+pub fn compile_host(items: Vec<syn::Item>) -> Result<libloading::Library> {
+    check_toolchain()?;
+    let file: syn::File = syn::parse_quote! {
+        extern crate interface;
+
+        // kernels may use the conversion traits without naming them
+        #[allow(unused_imports)]
+        use interface::{Channel, FromPixel, IntoPixel};
+
+        #(#items)*
+    };
+
+    let source = prettyplease::unparse(&file);
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("codegen.rs"), &source)?;
+    fs::write(dir.path().join("libinterface.rlib"), HOST_INTERFACE_RLIB)?;
+
+    let output = process::Command::new("rustup")
+        .current_dir(&dir)
+        .arg("run")
+        .arg(TOOLCHAIN)
+        .arg("rustc")
+        .arg("codegen.rs")
+        .arg("--target")
+        .arg(env!("HOST_TARGET"))
+        .arg("--crate-type=cdylib")
+        .arg("-O")
+        .arg("-L")
+        .arg(".")
+        .output()?;
 
     if output.status.success() {
-        Ok(fs::read_to_string(dir.path().join("codegen.ptx")).unwrap())
+        let path = dir.path().join(format!(
+            "{}codegen{}",
+            consts::DLL_PREFIX,
+            consts::DLL_SUFFIX
+        ));
+        // Safe because the library is our own generated code, which has no initialisation routines
+        unsafe { libloading::Library::new(path) }.map_err(Error::LibraryLoad)
     } else {
-        Err(())
+        Err(Error::Compile {
+            source,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+// Checks once per process that the toolchain can be run, so that a missing toolchain is reported as such instead of as
+// the failure of the first compilation
+fn check_toolchain() -> Result<()> {
+    static CHECKED: OnceLock<std::result::Result<(), String>> = OnceLock::new();
+    CHECKED
+        .get_or_init(|| toolchain_problem(TOOLCHAIN).map_or(Ok(()), Err))
+        .clone()
+        .map_err(|reason| Error::MissingToolchain {
+            toolchain: TOOLCHAIN,
+            reason,
+        })
+}
+
+// Describes why rustc of the toolchain cannot be run, if it cannot
+fn toolchain_problem(toolchain: &str) -> Option<String> {
+    match process::Command::new("rustup")
+        .arg("run")
+        .arg(toolchain)
+        .arg("rustc")
+        .arg("--version")
+        .output()
+    {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => Some(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Err(error) => Some(format!("rustup cannot be run: {error}")),
+    }
+}
+
+// Compiled ptx is cached in the directory given by CUDA_FUSION_CACHE_DIR, which defaults to cuda-fusion in the user's cache
+// directory. Setting it to an empty string disables the cache.
+#[cfg(feature = "cuda")]
+fn cache_dir() -> Option<PathBuf> {
+    cache_dir_from(|name| env::var_os(name))
+}
+
+// The cache directory with the environment variables read by `var`
+#[cfg(feature = "cuda")]
+fn cache_dir_from(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    match var("CUDA_FUSION_CACHE_DIR") {
+        Some(dir) if dir.is_empty() => None,
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|dir| dir.join("cuda-fusion")),
     }
 }
 
+// Hashes everything the ptx depends on, so that a changed kernel, toolchain or interface crate never hits a stale entry
+#[cfg(feature = "cuda")]
+fn cache_key(source: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        source.as_bytes(),
+        TOOLCHAIN.as_bytes(),
+        TARGET_CPU.as_bytes(),
+        INTERFACE_RLIB,
+    ] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Writes to a temporary file first, so that concurrent processes never read a partially written entry
+#[cfg(feature = "cuda")]
+fn store(path: &Path, ptx: &str) -> io::Result<()> {
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir)?;
+    let file = tempfile::NamedTempFile::new_in(dir)?;
+    fs::write(file.path(), ptx)?;
+    file.persist(path)?;
+    Ok(())
+}
+
 // the panic handler is copied from code supplies by Muybridge. I'm not sure what the original source is
+#[cfg(feature = "cuda")]
 fn panic_handler() -> syn::ItemFn {
     syn::parse_quote! {
         #[panic_handler]
@@ -104,3 +268,63 @@ fn panic_handler() -> syn::ItemFn {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_toolchain_is_described() {
+        assert!(toolchain_problem("cuda-fusion-no-such-toolchain").is_some());
+    }
+
+    // The cache directory with only the given environment variables set
+    #[cfg(feature = "cuda")]
+    fn cache_dir_with(vars: &[(&str, &str)]) -> Option<PathBuf> {
+        cache_dir_from(|name| {
+            vars.iter()
+                .find(|(var, _value)| *var == name)
+                .map(|(_var, value)| OsString::from(value))
+        })
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn cache_dir_fallbacks() {
+        let all = [
+            ("CUDA_FUSION_CACHE_DIR", "/explicit"),
+            ("XDG_CACHE_HOME", "/xdg"),
+            ("HOME", "/home/user"),
+        ];
+        assert_eq!(cache_dir_with(&all), Some(PathBuf::from("/explicit")));
+        assert_eq!(
+            cache_dir_with(&all[1..]),
+            Some(PathBuf::from("/xdg/cuda-fusion"))
+        );
+        assert_eq!(
+            cache_dir_with(&all[2..]),
+            Some(PathBuf::from("/home/user/.cache/cuda-fusion"))
+        );
+        assert_eq!(cache_dir_with(&[]), None);
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn empty_cache_dir_disables_the_cache() {
+        assert_eq!(
+            cache_dir_with(&[
+                ("CUDA_FUSION_CACHE_DIR", ""),
+                ("XDG_CACHE_HOME", "/xdg"),
+                ("HOME", "/home/user"),
+            ]),
+            None
+        );
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn cache_key_depends_on_the_source() {
+        assert_eq!(cache_key("fn kernel() {}"), cache_key("fn kernel() {}"));
+        assert_ne!(cache_key("fn kernel() {}"), cache_key("fn kernel() { }"));
+    }
+}
@@ -1,7 +1,13 @@
-use std::{collections::HashSet, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+};
 
-use crate::pixel::PixelType;
+use crate::{border::Border, pixel::PixelType, resize::ResizeFilter};
 
+// Nodes are always behind an Rc, so boxing the larger variant would only add an indirection
+#[allow(clippy::large_enum_variant)]
 pub enum Node {
     Input {
         name: String,
@@ -12,6 +18,20 @@ pub enum Node {
     Operation(Operation),
 }
 
+// A node computed into an output of a transformation
+#[derive(Clone)]
+pub struct Output(Rc<Node>);
+
+impl Output {
+    pub fn new(node: Rc<Node>) -> Self {
+        Self(node)
+    }
+
+    pub(crate) fn node(&self) -> &Node {
+        &self.0
+    }
+}
+
 pub enum Operation {
     MapPixel {
         dependency: Rc<Node>,
@@ -21,8 +41,10 @@ pub enum Operation {
     MapPatch {
         dependency: Rc<Node>,
         f: syn::ItemFn,
-        dimension: usize,
+        patch_width: usize,
+        patch_height: usize,
         pixel_type: PixelType,
+        border: Border<Vec<u8>>,
     },
     MapImage {
         dependency: Rc<Node>,
@@ -34,6 +56,46 @@ pub enum Operation {
     Flip {
         dependency: Rc<Node>,
     },
+    // The width x height rectangle of the dependency whose top left corner is at (x, y)
+    Crop {
+        dependency: Rc<Node>,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    Pad {
+        dependency: Rc<Node>,
+        left: usize,
+        right: usize,
+        top: usize,
+        bottom: usize,
+        border: Border<Vec<u8>>,
+    },
+    // Scales the dependency to width x height
+    Resize {
+        dependency: Rc<Node>,
+        width: usize,
+        height: usize,
+        filter: ResizeFilter,
+    },
+    // Converts every pixel between Rgb and a colour space, which is given by the pixel types
+    ConvertColourSpace {
+        dependency: Rc<Node>,
+        pixel_type: PixelType,
+    },
+    // Converts the planes of a video range frame with chroma subsampled by two in both directions
+    Nv12ToRgb {
+        dependency_y: Rc<Node>,
+        dependency_uv: Rc<Node>,
+        pixel_type: PixelType,
+    },
+    I420ToRgb {
+        dependency_y: Rc<Node>,
+        dependency_u: Rc<Node>,
+        dependency_v: Rc<Node>,
+        pixel_type: PixelType,
+    },
     HConcat {
         dependency_left: Rc<Node>,
         dependency_right: Rc<Node>,
@@ -65,6 +127,90 @@ pub fn toposort(roots: Vec<&Node>) -> Vec<&Node> {
     result
 }
 
+#[derive(Debug)]
+pub struct ValidationError {
+    // Dot separated path from an output to the offending node, e.g. `res3.left.dependency`
+    pub path: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+// Reports every problem in the graphs reachable from the outputs. Each node is reported at the first path it is found at.
+pub fn validate(outputs: &HashMap<String, &Node>) -> Result<(), Vec<ValidationError>> {
+    fn visit<'a>(
+        node: &'a Node,
+        path: String,
+        visited: &mut HashSet<*const Node>,
+        input_paths: &mut HashMap<&'a str, String>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if !visited.insert(node) {
+            return;
+        }
+
+        if let Node::Input {
+            name,
+            height: _,
+            width: _,
+            pixel_type: _,
+        } = node
+        {
+            if let Some(other_path) = input_paths.get(name.as_str()) {
+                errors.push(ValidationError {
+                    path: path.clone(),
+                    reason: format!(
+                        "input name `{name}` is already used by the input at {other_path}"
+                    ),
+                });
+            } else {
+                input_paths.insert(name, path.clone());
+            }
+        }
+
+        errors.extend(node.problems().into_iter().map(|reason| ValidationError {
+            path: path.clone(),
+            reason,
+        }));
+
+        for (label, dependency) in node.labelled_dependencies() {
+            visit(
+                dependency,
+                format!("{path}.{label}"),
+                visited,
+                input_paths,
+                errors,
+            );
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut input_paths = HashMap::new();
+    let mut errors = Vec::new();
+
+    let mut outputs = outputs.iter().collect::<Vec<_>>();
+    outputs.sort_by_key(|(name, _node)| *name);
+    for (name, node) in outputs {
+        visit(
+            node,
+            name.clone(),
+            &mut visited,
+            &mut input_paths,
+            &mut errors,
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
 impl Node {
     pub fn height(&self) -> usize {
         match self {
@@ -117,10 +263,43 @@ impl Node {
             Node::Operation(o) => o.dependencies(),
         }
     }
+
+    // Describes every reason the node is an impossible operation, assuming its dependencies are possible
+    fn problems(&self) -> Vec<String> {
+        match self {
+            Node::Input {
+                name: _,
+                height,
+                width,
+                pixel_type: _,
+            } if *width == 0 || *height == 0 => {
+                vec![format!("input must not be empty, but is {width}x{height}")]
+            }
+            Node::Input {
+                name: _,
+                height: _,
+                width: _,
+                pixel_type: _,
+            } => Vec::new(),
+            Node::Operation(o) => o.problems(),
+        }
+    }
+
+    fn labelled_dependencies(&self) -> Vec<(&'static str, &Self)> {
+        match self {
+            Node::Input {
+                name: _,
+                height: _,
+                width: _,
+                pixel_type: _,
+            } => Vec::new(),
+            Node::Operation(o) => o.labelled_dependencies(),
+        }
+    }
 }
 
 impl Operation {
-    fn height(&self) -> usize {
+    pub fn height(&self) -> usize {
         match self {
             Operation::MapPixel {
                 dependency: child,
@@ -131,8 +310,10 @@ impl Operation {
             Operation::MapPatch {
                 dependency: child,
                 f: _,
-                dimension: _,
+                patch_width: _,
+                patch_height: _,
                 pixel_type: _,
+                border: _,
             } => child.height(),
 
             Operation::MapImage {
@@ -145,13 +326,55 @@ impl Operation {
 
             Operation::Flip { dependency } => dependency.height(),
 
+            Operation::Crop {
+                dependency: _,
+                x: _,
+                y: _,
+                width: _,
+                height,
+            } => *height,
+
+            Operation::Pad {
+                dependency,
+                left: _,
+                right: _,
+                top,
+                bottom,
+                border: _,
+                // saturating, as the consumers of an overflowing pad are validated before it is reported
+            } => top
+                .saturating_add(dependency.height())
+                .saturating_add(*bottom),
+
+            Operation::Resize {
+                dependency: _,
+                width: _,
+                height,
+                filter: _,
+            } => *height,
+
+            Operation::ConvertColourSpace {
+                dependency,
+                pixel_type: _,
+            } => dependency.height(),
+
+            Operation::Nv12ToRgb {
+                dependency_y,
+                dependency_uv: _,
+                pixel_type: _,
+            } => dependency_y.height(),
+
+            Operation::I420ToRgb {
+                dependency_y,
+                dependency_u: _,
+                dependency_v: _,
+                pixel_type: _,
+            } => dependency_y.height(),
+
             Operation::HConcat {
                 dependency_left,
-                dependency_right,
-            } => {
-                assert_eq!(dependency_left.height(), dependency_right.height());
-                dependency_left.height()
-            }
+                dependency_right: _,
+            } => dependency_left.height(),
 
             Operation::VConcat {
                 dependency_top,
@@ -160,7 +383,7 @@ impl Operation {
         }
     }
 
-    fn width(&self) -> usize {
+    pub fn width(&self) -> usize {
         match self {
             Operation::MapPixel {
                 dependency: child,
@@ -171,8 +394,10 @@ impl Operation {
             Operation::MapPatch {
                 dependency: child,
                 f: _,
-                dimension: _,
+                patch_width: _,
+                patch_height: _,
                 pixel_type: _,
+                border: _,
             } => child.width(),
 
             Operation::MapImage {
@@ -185,6 +410,50 @@ impl Operation {
 
             Operation::Flip { dependency } => dependency.width(),
 
+            Operation::Crop {
+                dependency: _,
+                x: _,
+                y: _,
+                width,
+                height: _,
+            } => *width,
+
+            Operation::Pad {
+                dependency,
+                left,
+                right,
+                top: _,
+                bottom: _,
+                border: _,
+            } => left
+                .saturating_add(dependency.width())
+                .saturating_add(*right),
+
+            Operation::Resize {
+                dependency: _,
+                width,
+                height: _,
+                filter: _,
+            } => *width,
+
+            Operation::ConvertColourSpace {
+                dependency,
+                pixel_type: _,
+            } => dependency.width(),
+
+            Operation::Nv12ToRgb {
+                dependency_y,
+                dependency_uv: _,
+                pixel_type: _,
+            } => dependency_y.width(),
+
+            Operation::I420ToRgb {
+                dependency_y,
+                dependency_u: _,
+                dependency_v: _,
+                pixel_type: _,
+            } => dependency_y.width(),
+
             Operation::HConcat {
                 dependency_left,
                 dependency_right,
@@ -192,15 +461,12 @@ impl Operation {
 
             Operation::VConcat {
                 dependency_top,
-                dependency_bottom,
-            } => {
-                assert_eq!(dependency_top.width(), dependency_bottom.width());
-                dependency_top.width()
-            }
+                dependency_bottom: _,
+            } => dependency_top.width(),
         }
     }
 
-    fn pixel_type(&self) -> PixelType {
+    pub fn pixel_type(&self) -> PixelType {
         match self {
             Operation::MapPixel {
                 dependency: _,
@@ -211,8 +477,10 @@ impl Operation {
             Operation::MapPatch {
                 dependency: _,
                 f: _,
-                dimension: _,
+                patch_width: _,
+                patch_height: _,
                 pixel_type,
+                border: _,
             } => *pixel_type,
 
             Operation::MapImage {
@@ -225,25 +493,234 @@ impl Operation {
 
             Operation::Flip { dependency } => dependency.pixel_type(),
 
+            Operation::Crop {
+                dependency,
+                x: _,
+                y: _,
+                width: _,
+                height: _,
+            } => dependency.pixel_type(),
+
+            Operation::Pad {
+                dependency,
+                left: _,
+                right: _,
+                top: _,
+                bottom: _,
+                border: _,
+            } => dependency.pixel_type(),
+
+            Operation::Resize {
+                dependency,
+                width: _,
+                height: _,
+                filter: _,
+            } => dependency.pixel_type(),
+
+            Operation::ConvertColourSpace {
+                dependency: _,
+                pixel_type,
+            } => *pixel_type,
+
+            Operation::Nv12ToRgb {
+                dependency_y: _,
+                dependency_uv: _,
+                pixel_type,
+            } => *pixel_type,
+
+            Operation::I420ToRgb {
+                dependency_y: _,
+                dependency_u: _,
+                dependency_v: _,
+                pixel_type,
+            } => *pixel_type,
+
+            Operation::HConcat {
+                dependency_left,
+                dependency_right: _,
+            } => dependency_left.pixel_type(),
+
+            Operation::VConcat {
+                dependency_top,
+                dependency_bottom: _,
+            } => dependency_top.pixel_type(),
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self {
+            Operation::MapPatch {
+                dependency: _,
+                f: _,
+                patch_width,
+                patch_height,
+                pixel_type: _,
+                border: _,
+            } => {
+                if patch_width % 2 == 0 || patch_height % 2 == 0 {
+                    problems.push(format!(
+                        "map_patch requires odd patch dimensions, but got {patch_width}x{patch_height}"
+                    ));
+                }
+            }
+
+            Operation::MapImage {
+                dependency: _,
+                f: _,
+                height,
+                width,
+                pixel_type: _,
+            } => {
+                if *width == 0 || *height == 0 {
+                    problems.push(format!(
+                        "map_image must not produce an empty image, but produces {width}x{height}"
+                    ));
+                }
+            }
+
             Operation::HConcat {
                 dependency_left,
                 dependency_right,
             } => {
-                assert_eq!(dependency_left.pixel_type(), dependency_right.pixel_type());
-                dependency_left.pixel_type()
+                if dependency_left.height() != dependency_right.height() {
+                    problems.push(format!(
+                        "h_concat requires equal heights, but got {} and {}",
+                        dependency_left.height(),
+                        dependency_right.height()
+                    ));
+                }
+                if dependency_left.pixel_type() != dependency_right.pixel_type() {
+                    problems.push(format!(
+                        "h_concat requires equal pixel types, but got {:?} and {:?}",
+                        dependency_left.pixel_type(),
+                        dependency_right.pixel_type()
+                    ));
+                }
             }
 
             Operation::VConcat {
                 dependency_top,
                 dependency_bottom,
             } => {
-                assert_eq!(dependency_top.pixel_type(), dependency_bottom.pixel_type());
-                dependency_top.pixel_type()
+                if dependency_top.width() != dependency_bottom.width() {
+                    problems.push(format!(
+                        "v_concat requires equal widths, but got {} and {}",
+                        dependency_top.width(),
+                        dependency_bottom.width()
+                    ));
+                }
+                if dependency_top.pixel_type() != dependency_bottom.pixel_type() {
+                    problems.push(format!(
+                        "v_concat requires equal pixel types, but got {:?} and {:?}",
+                        dependency_top.pixel_type(),
+                        dependency_bottom.pixel_type()
+                    ));
+                }
+            }
+
+            Operation::Crop {
+                dependency,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if *width == 0 || *height == 0 {
+                    problems.push(format!(
+                        "crop must not produce an empty image, but produces {width}x{height}"
+                    ));
+                }
+                if x + width > dependency.width() || y + height > dependency.height() {
+                    problems.push(format!(
+                        "crop of {width}x{height} at ({x}, {y}) is outside of the {}x{} image",
+                        dependency.width(),
+                        dependency.height()
+                    ));
+                }
+            }
+
+            Operation::Pad {
+                dependency,
+                left,
+                right,
+                top,
+                bottom,
+                border: _,
+            } => {
+                let width = left
+                    .checked_add(dependency.width())
+                    .and_then(|width| width.checked_add(*right));
+                let height = top
+                    .checked_add(dependency.height())
+                    .and_then(|height| height.checked_add(*bottom));
+                match (width, height) {
+                    (Some(width), Some(height)) => {
+                        if width == 0 || height == 0 {
+                            problems.push(format!(
+                                "pad must not produce an empty image, but produces {width}x{height}"
+                            ));
+                        }
+                    }
+                    _ => problems.push(format!(
+                        "pad of the {}x{} image by {left}, {right}, {top}, {bottom} overflows its size",
+                        dependency.width(),
+                        dependency.height()
+                    )),
+                }
+            }
+
+            Operation::Resize {
+                dependency: _,
+                width,
+                height,
+                filter: _,
+            } => {
+                if *width == 0 || *height == 0 {
+                    problems.push(format!(
+                        "resize must not produce an empty image, but produces {width}x{height}"
+                    ));
+                }
+            }
+
+            Operation::Nv12ToRgb {
+                dependency_y,
+                dependency_uv,
+                pixel_type: _,
+            } => problems.extend(chroma_problem("uv", dependency_y, dependency_uv)),
+
+            Operation::I420ToRgb {
+                dependency_y,
+                dependency_u,
+                dependency_v,
+                pixel_type: _,
+            } => {
+                problems.extend(chroma_problem("u", dependency_y, dependency_u));
+                problems.extend(chroma_problem("v", dependency_y, dependency_v));
             }
+
+            Operation::MapPixel {
+                dependency: _,
+                f: _,
+                pixel_type: _,
+            }
+            | Operation::Flip { dependency: _ }
+            | Operation::ConvertColourSpace {
+                dependency: _,
+                pixel_type: _,
+            } => (),
         }
+        problems
     }
 
     fn dependencies(&self) -> Vec<&Node> {
+        self.labelled_dependencies()
+            .into_iter()
+            .map(|(_label, dependency)| dependency)
+            .collect()
+    }
+
+    fn labelled_dependencies(&self) -> Vec<(&'static str, &Node)> {
         match self {
             Operation::MapPixel {
                 dependency,
@@ -253,8 +730,10 @@ impl Operation {
             | Operation::MapPatch {
                 dependency,
                 f: _,
-                dimension: _,
+                patch_width: _,
+                patch_height: _,
                 pixel_type: _,
+                border: _,
             }
             | Operation::MapImage {
                 dependency,
@@ -263,17 +742,192 @@ impl Operation {
                 width: _,
                 pixel_type: _,
             }
-            | Operation::Flip { dependency } => vec![&**dependency],
+            | Operation::Flip { dependency }
+            | Operation::Crop {
+                dependency,
+                x: _,
+                y: _,
+                width: _,
+                height: _,
+            }
+            | Operation::Pad {
+                dependency,
+                left: _,
+                right: _,
+                top: _,
+                bottom: _,
+                border: _,
+            }
+            | Operation::Resize {
+                dependency,
+                width: _,
+                height: _,
+                filter: _,
+            }
+            | Operation::ConvertColourSpace {
+                dependency,
+                pixel_type: _,
+            } => vec![("dependency", dependency)],
+
+            Operation::Nv12ToRgb {
+                dependency_y,
+                dependency_uv,
+                pixel_type: _,
+            } => vec![("y", dependency_y), ("uv", dependency_uv)],
+
+            Operation::I420ToRgb {
+                dependency_y,
+                dependency_u,
+                dependency_v,
+                pixel_type: _,
+            } => vec![
+                ("y", dependency_y),
+                ("u", dependency_u),
+                ("v", dependency_v),
+            ],
 
             Operation::HConcat {
                 dependency_left,
                 dependency_right,
-            } => vec![dependency_left, dependency_right],
+            } => vec![("left", dependency_left), ("right", dependency_right)],
 
             Operation::VConcat {
                 dependency_top,
                 dependency_bottom,
-            } => vec![dependency_top, dependency_bottom],
+            } => vec![("top", dependency_top), ("bottom", dependency_bottom)],
         }
     }
 }
+
+// Chroma planes have half the size of the luma plane, rounded up
+fn chroma_problem(label: &str, luma: &Node, chroma: &Node) -> Option<String> {
+    let expected = (luma.width().div_ceil(2), luma.height().div_ceil(2));
+    let found = (chroma.width(), chroma.height());
+    (expected != found).then(|| {
+        format!(
+            "the {label} plane of a {}x{} frame must be {}x{}, but is {}x{}",
+            luma.width(),
+            luma.height(),
+            expected.0,
+            expected.1,
+            found.0,
+            found.1
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use interface::Rgb;
+
+    use super::{validate, Output};
+    use crate::{new_input, Border};
+
+    // Validates the outputs, returning each error as `path: reason`
+    fn errors(outputs: Vec<(&str, Output)>) -> Vec<String> {
+        let outputs = outputs
+            .iter()
+            .map(|(name, output)| (name.to_string(), output.node()))
+            .collect::<HashMap<_, _>>();
+        match validate(&outputs) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_graph() {
+        let img = new_input::<Rgb<u8>>("img".to_string(), 4, 3);
+        let out = img.h_concat(&img.flip()).crop(1, 0, 6, 3);
+        assert!(errors(vec![("out", out.into_output())]).is_empty());
+    }
+
+    #[test]
+    fn h_concat_of_unequal_heights() {
+        let left = new_input::<Rgb<u8>>("left".to_string(), 4, 3);
+        let right = new_input::<Rgb<u8>>("right".to_string(), 4, 2);
+        assert_eq!(
+            errors(vec![("out", left.h_concat(&right).into_output())]),
+            ["out: h_concat requires equal heights, but got 3 and 2"]
+        );
+    }
+
+    #[test]
+    fn crop_outside_of_image() {
+        let img = new_input::<Rgb<u8>>("img".to_string(), 4, 3);
+        assert_eq!(
+            errors(vec![("out", img.crop(2, 1, 3, 2).flip().into_output())]),
+            ["out.dependency: crop of 3x2 at (2, 1) is outside of the 4x3 image"]
+        );
+    }
+
+    #[test]
+    fn empty_input() {
+        let img = new_input::<Rgb<u8>>("img".to_string(), 0, 3);
+        assert_eq!(
+            errors(vec![("out", img.into_output())]),
+            ["out: input must not be empty, but is 0x3"]
+        );
+    }
+
+    #[test]
+    fn duplicate_input_name() {
+        let first = new_input::<Rgb<u8>>("img".to_string(), 4, 3);
+        let second = new_input::<Rgb<u8>>("img".to_string(), 4, 3);
+        assert_eq!(
+            errors(vec![
+                ("a", first.into_output()),
+                ("b", second.into_output())
+            ]),
+            ["b: input name `img` is already used by the input at a"]
+        );
+    }
+
+    #[test]
+    fn shared_node_is_reported_once() {
+        let left = new_input::<Rgb<u8>>("left".to_string(), 4, 3);
+        let right = new_input::<Rgb<u8>>("right".to_string(), 4, 2);
+        let concat = left.h_concat(&right);
+        assert_eq!(
+            errors(vec![
+                ("a", concat.flip().into_output()),
+                ("b", concat.into_output())
+            ]),
+            ["a.dependency: h_concat requires equal heights, but got 3 and 2"]
+        );
+    }
+
+    #[test]
+    fn empty_pad() {
+        let img = new_input::<Rgb<u8>>("img".to_string(), 0, 3);
+        assert_eq!(
+            errors(vec![(
+                "out",
+                img.pad(0, 0, 1, 1, Border::Replicate).into_output()
+            )]),
+            [
+                "out: pad must not produce an empty image, but produces 0x5",
+                "out.dependency: input must not be empty, but is 0x3"
+            ]
+        );
+    }
+
+    #[test]
+    fn overflowing_pad() {
+        let img = new_input::<Rgb<u8>>("img".to_string(), 4, 3);
+        assert_eq!(
+            errors(vec![(
+                "out",
+                img.pad(usize::MAX, 0, 0, 0, Border::Replicate)
+                    .flip()
+                    .into_output()
+            )]),
+            [format!(
+                "out.dependency: pad of the 4x3 image by {}, 0, 0, 0 overflows its size",
+                usize::MAX
+            )]
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use itertools::Itertools;
+use quote::{format_ident, quote};
+use syn::parse_quote;
+
+use crate::{
+    buffer::{check_inputs, Buffer, RequiredInput},
+    codegen::{self, Kernel, Target},
+    compiler::compile_host,
+    computational_dependency_graph as cdg,
+    deduplication::deduplicate,
+    Error, Result,
+};
+use cdg::{toposort, validate, Node, Output};
+
+// Host buffers are tightly packed, so every pitch is computed with an alignment of one
+const ALIGNMENT: usize = 1;
+
+// The default block size of the device, so that patches are read from tiles of the same size as there
+const BLOCK_WIDTH: usize = 16;
+const BLOCK_HEIGHT: usize = 16;
+
+// Executes a transformation on the host, for use as a reference for the results computed on the device. The kernels are
+// generated by the same code as for the device, but without fusion and with loops over the blocks and their pixels in
+// place of the thread indices. They are compiled as ordinary Rust into a dynamic library that operates on host buffers,
+// which needs the nightly-2022-10-13 toolchain to be installed with rustup, just as for the device.
+pub struct CpuTransformation {
+    input_buffers: HashMap<String, Buffer>,
+    output_buffers: HashMap<String, Buffer>,
+    // the generated code refers to every buffer, including the intermediate ones
+    _buffers: Vec<Buffer>,
+    library: libloading::Library,
+}
+
+impl CpuTransformation {
+    pub fn new(outputs: HashMap<String, Output>) -> Result<Self> {
+        let mut input_buffers = HashMap::new();
+        let mut buffers: HashMap<*const Node, Buffer> = HashMap::new();
+        let mut item_fns = Vec::new();
+        // the calls of the kernels with their parameters, in topological order
+        let mut calls = Vec::new();
+
+        let outputs: HashMap<String, &Node> = outputs
+            .iter()
+            .map(|(name, output)| (name.clone(), output.node()))
+            .collect();
+
+        // validated before deduplication, which would merge identical inputs of the same name
+        validate(&outputs).map_err(Error::InvalidGraph)?;
+
+        let deduplicated = deduplicate(&outputs);
+        let outputs: HashMap<String, &Node> = outputs
+            .into_iter()
+            .map(|(name, node)| (name, &*deduplicated[&(node as *const _)]))
+            .collect();
+
+        for (i, node) in toposort(outputs.values().copied().collect_vec())
+            .into_iter()
+            .enumerate()
+        {
+            let buffer = Buffer::new(node.width(), node.height(), node.pixel_type());
+
+            match node {
+                Node::Input { name, .. } => {
+                    assert!(input_buffers.insert(name.clone(), buffer.clone()).is_none());
+                }
+
+                // every dependency has a buffer, so nothing is fused and every node is checked on its own
+                Node::Operation(_) => {
+                    let name = format_ident!("node_{i}");
+                    let Kernel {
+                        item_fn,
+                        params,
+                        geometry: _,
+                    } = codegen::kernel(
+                        node,
+                        buffer.as_ptr() as usize,
+                        |dependency| Some(buffers[&(dependency as *const _)].as_ptr() as usize),
+                        ALIGNMENT,
+                        BLOCK_WIDTH,
+                        BLOCK_HEIGHT,
+                        Target::Host(name.clone()),
+                    );
+                    item_fns.push(item_fn);
+                    calls.push(quote! { #name(#(#params),*); });
+                }
+            }
+
+            assert!(buffers.insert(node, buffer).is_none());
+        }
+
+        let run: syn::ItemFn = parse_quote! {
+            #[no_mangle]
+            pub unsafe extern "C" fn run() {
+                #(#calls)*
+            }
+        };
+        item_fns.push(run);
+        let items = item_fns.into_iter().map(syn::Item::Fn).collect_vec();
+
+        let output_buffers = outputs
+            .into_iter()
+            .map(|(name, node)| (name, buffers[&(node as *const _)].clone()))
+            .collect();
+
+        Ok(Self {
+            input_buffers,
+            output_buffers,
+            _buffers: buffers.into_values().collect(),
+            library: compile_host(items)?,
+        })
+    }
+
+    pub fn inputs(&self) -> Vec<RequiredInput> {
+        self.input_buffers
+            .iter()
+            .sorted_by_key(|(name, _buffer)| *name)
+            .map(|(name, buffer)| buffer.required_input(name))
+            .collect()
+    }
+
+    pub fn call(
+        &mut self,
+        inputs: HashMap<String, DynamicImage>,
+    ) -> Result<HashMap<String, DynamicImage>> {
+        check_inputs(&self.input_buffers, &inputs)?;
+
+        for (name, buffer) in self.input_buffers.iter_mut() {
+            // Safe because the buffer is currently not being read from
+            unsafe {
+                buffer.copy_from_dynamic_image(&inputs[name]);
+            }
+        }
+
+        // Safe if the kernels are written correctly
+        unsafe {
+            let run = self
+                .library
+                .get::<unsafe extern "C" fn()>(b"run")
+                .map_err(Error::LibraryLoad)?;
+            run();
+        }
+
+        // Safe because the buffers are not being written to
+        unsafe {
+            Ok(self
+                .output_buffers
+                .iter()
+                .map(|(name, buffer)| (name.clone(), buffer.to_dynamic_image()))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::{DynamicImage, RgbImage};
+    use interface::{Patch, Rgb};
+    use macros::{map_patch_kernel, map_pixel_kernel};
+
+    use super::CpuTransformation;
+    use crate::{new_input, Border, Error, Node};
+
+    #[map_pixel_kernel]
+    fn invert(px: Rgb<u8>) -> Rgb<u8> {
+        interface::Rgb {
+            r: 255 - px.r,
+            g: 255 - px.g,
+            b: 255 - px.b,
+        }
+    }
+
+    #[map_patch_kernel]
+    fn left_neighbour(patch: Patch<3, 1, Rgb<u8>>) -> Rgb<u8> {
+        patch.at(-1, 0)
+    }
+
+    #[map_patch_kernel]
+    fn upper_right_neighbour(patch: Patch<5, 3, Rgb<u8>>) -> Rgb<u8> {
+        patch.at(2, -1)
+    }
+
+    #[map_patch_kernel]
+    fn column_sum(patch: Patch<1, 3, Rgb<u8>>) -> Rgb<u8> {
+        let mut sum = interface::Rgb { r: 0, g: 0, b: 0 };
+        for (_dx, _dy, px) in patch.iter() {
+            sum = sum + px;
+        }
+        sum
+    }
+
+    const WIDTH: u32 = 5;
+    const HEIGHT: u32 = 4;
+
+    fn image() -> RgbImage {
+        RgbImage::from_fn(WIDTH, HEIGHT, |col, row| {
+            // small enough that the sum of three pixels does not overflow
+            image::Rgb([(col * 20) as u8, (row * 20) as u8, (col * row) as u8])
+        })
+    }
+
+    fn run(node: Node<Rgb<u8>>) -> RgbImage {
+        run_on(node, image())
+    }
+
+    fn run_on(node: Node<Rgb<u8>>, image: RgbImage) -> RgbImage {
+        let outputs = HashMap::from([("out".to_string(), node.into_output())]);
+        let mut transformation = CpuTransformation::new(outputs).unwrap();
+        let inputs = HashMap::from([("img".to_string(), DynamicImage::ImageRgb8(image))]);
+        let mut outputs = transformation.call(inputs).unwrap();
+        outputs.remove("out").unwrap().into_rgb8()
+    }
+
+    fn input() -> Node<Rgb<u8>> {
+        new_input("img".into(), WIDTH as usize, HEIGHT as usize)
+    }
+
+    #[test]
+    fn map_pixel() {
+        let expected = RgbImage::from_fn(WIDTH, HEIGHT, |col, row| {
+            let image::Rgb([r, g, b]) = *image().get_pixel(col, row);
+            image::Rgb([255 - r, 255 - g, 255 - b])
+        });
+        assert_eq!(run(input().map_pixel(&invert)), expected);
+    }
+
+    #[test]
+    fn map_patch_with_constant_border() {
+        let border = Rgb { r: 1, g: 2, b: 3 };
+        let expected = RgbImage::from_fn(WIDTH, HEIGHT, |col, row| match col {
+            0 => image::Rgb([1, 2, 3]),
+            _ => *image().get_pixel(col - 1, row),
+        });
+        assert_eq!(
+            run(input().map_patch_with_border(&left_neighbour, Border::Constant(border))),
+            expected
+        );
+    }
+
+    #[test]
+    fn map_patch_with_replicated_border() {
+        let expected = RgbImage::from_fn(WIDTH, HEIGHT, |col, row| {
+            let image = image();
+            let rows = [row.saturating_sub(1), row, (row + 1).min(HEIGHT - 1)];
+            let sum = |channel: usize| {
+                rows.iter()
+                    .map(|row| image.get_pixel(col, *row)[channel])
+                    .sum()
+            };
+            image::Rgb([sum(0), sum(1), sum(2)])
+        });
+        assert_eq!(
+            run(input().map_patch_with_border(&column_sum, Border::Replicate)),
+            expected
+        );
+    }
+
+    #[test]
+    fn map_patch_of_map_pixel() {
+        let expected = RgbImage::from_fn(WIDTH, HEIGHT, |col, row| match col {
+            // the zero border of map_patch
+            0 => image::Rgb([0, 0, 0]),
+            _ => {
+                let image::Rgb([r, g, b]) = *image().get_pixel(col - 1, row);
+                image::Rgb([255 - r, 255 - g, 255 - b])
+            }
+        });
+        assert_eq!(
+            run(input().map_pixel(&invert).map_patch(&left_neighbour)),
+            expected
+        );
+    }
+
+    // Neither dimension is a multiple of the block size, so the tiles of the edge blocks are partly outside of the image
+    #[test]
+    fn map_patch_across_blocks() {
+        let (width, height) = (37, 21);
+        let image = RgbImage::from_fn(width, height, |col, row| {
+            image::Rgb([col as u8, row as u8, (col * row % 256) as u8])
+        });
+        let expected = RgbImage::from_fn(width, height, |col, row| {
+            *image.get_pixel((col + 2) % width, (row + height - 1) % height)
+        });
+        let node = new_input("img".into(), width as usize, height as usize)
+            .map_patch_with_border(&upper_right_neighbour, Border::Wrap);
+        assert_eq!(run_on(node, image), expected);
+    }
+
+    #[test]
+    fn identical_inputs_of_the_same_name() {
+        let outputs = HashMap::from([
+            (
+                "a".to_string(),
+                new_input::<Rgb<u8>>("img".into(), 4, 3).into_output(),
+            ),
+            (
+                "b".to_string(),
+                new_input::<Rgb<u8>>("img".into(), 4, 3).into_output(),
+            ),
+        ]);
+        match CpuTransformation::new(outputs) {
+            Err(Error::InvalidGraph(errors)) => assert_eq!(
+                errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+                ["b: input name `img` is already used by the input at a"]
+            ),
+            _ => panic!("expected the duplicate input name to be rejected"),
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use std::{collections::HashMap, rc::Rc};
+
+use itertools::Itertools;
+
+use crate::{
+    border::Border,
+    computational_dependency_graph::{toposort, Node, Operation},
+    pixel::PixelType,
+    resize::ResizeFilter,
+};
+
+// The contents of a node, with its dependencies identified by their deduplicated nodes. Two nodes with equal keys compute
+// the same image, since kernels are compared by their tokens and the dependencies are already deduplicated.
+#[derive(PartialEq, Eq, Hash)]
+enum Key<'a> {
+    Input {
+        name: &'a str,
+        width: usize,
+        height: usize,
+        pixel_type: PixelType,
+    },
+    MapPixel {
+        dependency: *const Node,
+        f: &'a syn::ItemFn,
+        pixel_type: PixelType,
+    },
+    MapPatch {
+        dependency: *const Node,
+        f: &'a syn::ItemFn,
+        patch_width: usize,
+        patch_height: usize,
+        pixel_type: PixelType,
+        border: &'a Border<Vec<u8>>,
+    },
+    MapImage {
+        dependency: *const Node,
+        f: &'a syn::ItemFn,
+        width: usize,
+        height: usize,
+        pixel_type: PixelType,
+    },
+    Flip {
+        dependency: *const Node,
+    },
+    Crop {
+        dependency: *const Node,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    Pad {
+        dependency: *const Node,
+        left: usize,
+        right: usize,
+        top: usize,
+        bottom: usize,
+        border: &'a Border<Vec<u8>>,
+    },
+    Resize {
+        dependency: *const Node,
+        width: usize,
+        height: usize,
+        filter: ResizeFilter,
+    },
+    ConvertColourSpace {
+        dependency: *const Node,
+        pixel_type: PixelType,
+    },
+    Nv12ToRgb {
+        dependency_y: *const Node,
+        dependency_uv: *const Node,
+        pixel_type: PixelType,
+    },
+    I420ToRgb {
+        dependency_y: *const Node,
+        dependency_u: *const Node,
+        dependency_v: *const Node,
+        pixel_type: PixelType,
+    },
+    HConcat {
+        dependency_left: *const Node,
+        dependency_right: *const Node,
+    },
+    VConcat {
+        dependency_top: *const Node,
+        dependency_bottom: *const Node,
+    },
+}
+
+// Rebuilds the graphs reachable from the outputs with structurally identical nodes merged into one, so that separately
+// built but identical sub-pipelines are only computed once. Returns the deduplicated node of every reachable node.
+pub fn deduplicate(outputs: &HashMap<String, &Node>) -> HashMap<*const Node, Rc<Node>> {
+    // maps every original node to its deduplicated node
+    let mut deduplicated: HashMap<*const Node, Rc<Node>> = HashMap::new();
+    let mut nodes: HashMap<Key, Rc<Node>> = HashMap::new();
+
+    for node in toposort(outputs.values().copied().collect_vec()) {
+        let dependency = |dependency: &Rc<Node>| deduplicated[&Rc::as_ptr(dependency)].clone();
+
+        let key = match node {
+            Node::Input {
+                name,
+                width,
+                height,
+                pixel_type,
+            } => Key::Input {
+                name,
+                width: *width,
+                height: *height,
+                pixel_type: *pixel_type,
+            },
+
+            Node::Operation(operation) => match operation {
+                Operation::MapPixel {
+                    dependency: d,
+                    f,
+                    pixel_type,
+                } => Key::MapPixel {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    f,
+                    pixel_type: *pixel_type,
+                },
+
+                Operation::MapPatch {
+                    dependency: d,
+                    f,
+                    patch_width,
+                    patch_height,
+                    pixel_type,
+                    border,
+                } => Key::MapPatch {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    f,
+                    patch_width: *patch_width,
+                    patch_height: *patch_height,
+                    pixel_type: *pixel_type,
+                    border,
+                },
+
+                Operation::MapImage {
+                    dependency: d,
+                    f,
+                    width,
+                    height,
+                    pixel_type,
+                } => Key::MapImage {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    f,
+                    width: *width,
+                    height: *height,
+                    pixel_type: *pixel_type,
+                },
+
+                Operation::Flip { dependency: d } => Key::Flip {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                },
+
+                Operation::Crop {
+                    dependency: d,
+                    x,
+                    y,
+                    width,
+                    height,
+                } => Key::Crop {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    x: *x,
+                    y: *y,
+                    width: *width,
+                    height: *height,
+                },
+
+                Operation::Pad {
+                    dependency: d,
+                    left,
+                    right,
+                    top,
+                    bottom,
+                    border,
+                } => Key::Pad {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    left: *left,
+                    right: *right,
+                    top: *top,
+                    bottom: *bottom,
+                    border,
+                },
+
+                Operation::Resize {
+                    dependency: d,
+                    width,
+                    height,
+                    filter,
+                } => Key::Resize {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    width: *width,
+                    height: *height,
+                    filter: *filter,
+                },
+
+                Operation::ConvertColourSpace {
+                    dependency: d,
+                    pixel_type,
+                } => Key::ConvertColourSpace {
+                    dependency: Rc::as_ptr(&dependency(d)),
+                    pixel_type: *pixel_type,
+                },
+
+                Operation::Nv12ToRgb {
+                    dependency_y,
+                    dependency_uv,
+                    pixel_type,
+                } => Key::Nv12ToRgb {
+                    dependency_y: Rc::as_ptr(&dependency(dependency_y)),
+                    dependency_uv: Rc::as_ptr(&dependency(dependency_uv)),
+                    pixel_type: *pixel_type,
+                },
+
+                Operation::I420ToRgb {
+                    dependency_y,
+                    dependency_u,
+                    dependency_v,
+                    pixel_type,
+                } => Key::I420ToRgb {
+                    dependency_y: Rc::as_ptr(&dependency(dependency_y)),
+                    dependency_u: Rc::as_ptr(&dependency(dependency_u)),
+                    dependency_v: Rc::as_ptr(&dependency(dependency_v)),
+                    pixel_type: *pixel_type,
+                },
+
+                Operation::HConcat {
+                    dependency_left,
+                    dependency_right,
+                } => Key::HConcat {
+                    dependency_left: Rc::as_ptr(&dependency(dependency_left)),
+                    dependency_right: Rc::as_ptr(&dependency(dependency_right)),
+                },
+
+                Operation::VConcat {
+                    dependency_top,
+                    dependency_bottom,
+                } => Key::VConcat {
+                    dependency_top: Rc::as_ptr(&dependency(dependency_top)),
+                    dependency_bottom: Rc::as_ptr(&dependency(dependency_bottom)),
+                },
+            },
+        };
+
+        let rebuilt = nodes
+            .entry(key)
+            .or_insert_with(|| Rc::new(rebuild(node, &deduplicated)))
+            .clone();
+        assert!(deduplicated.insert(node, rebuilt).is_none());
+    }
+
+    deduplicated
+}
+
+// Copies the node with its dependencies replaced by their deduplicated nodes
+fn rebuild(node: &Node, deduplicated: &HashMap<*const Node, Rc<Node>>) -> Node {
+    let dependency = |dependency: &Rc<Node>| deduplicated[&Rc::as_ptr(dependency)].clone();
+
+    match node {
+        Node::Input {
+            name,
+            width,
+            height,
+            pixel_type,
+        } => Node::Input {
+            name: name.clone(),
+            width: *width,
+            height: *height,
+            pixel_type: *pixel_type,
+        },
+
+        Node::Operation(operation) => Node::Operation(match operation {
+            Operation::MapPixel {
+                dependency: d,
+                f,
+                pixel_type,
+            } => Operation::MapPixel {
+                dependency: dependency(d),
+                f: f.clone(),
+                pixel_type: *pixel_type,
+            },
+
+            Operation::MapPatch {
+                dependency: d,
+                f,
+                patch_width,
+                patch_height,
+                pixel_type,
+                border,
+            } => Operation::MapPatch {
+                dependency: dependency(d),
+                f: f.clone(),
+                patch_width: *patch_width,
+                patch_height: *patch_height,
+                pixel_type: *pixel_type,
+                border: border.clone(),
+            },
+
+            Operation::MapImage {
+                dependency: d,
+                f,
+                width,
+                height,
+                pixel_type,
+            } => Operation::MapImage {
+                dependency: dependency(d),
+                f: f.clone(),
+                width: *width,
+                height: *height,
+                pixel_type: *pixel_type,
+            },
+
+            Operation::Flip { dependency: d } => Operation::Flip {
+                dependency: dependency(d),
+            },
+
+            Operation::Crop {
+                dependency: d,
+                x,
+                y,
+                width,
+                height,
+            } => Operation::Crop {
+                dependency: dependency(d),
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            },
+
+            Operation::Pad {
+                dependency: d,
+                left,
+                right,
+                top,
+                bottom,
+                border,
+            } => Operation::Pad {
+                dependency: dependency(d),
+                left: *left,
+                right: *right,
+                top: *top,
+                bottom: *bottom,
+                border: border.clone(),
+            },
+
+            Operation::Resize {
+                dependency: d,
+                width,
+                height,
+                filter,
+            } => Operation::Resize {
+                dependency: dependency(d),
+                width: *width,
+                height: *height,
+                filter: *filter,
+            },
+
+            Operation::ConvertColourSpace {
+                dependency: d,
+                pixel_type,
+            } => Operation::ConvertColourSpace {
+                dependency: dependency(d),
+                pixel_type: *pixel_type,
+            },
+
+            Operation::Nv12ToRgb {
+                dependency_y,
+                dependency_uv,
+                pixel_type,
+            } => Operation::Nv12ToRgb {
+                dependency_y: dependency(dependency_y),
+                dependency_uv: dependency(dependency_uv),
+                pixel_type: *pixel_type,
+            },
+
+            Operation::I420ToRgb {
+                dependency_y,
+                dependency_u,
+                dependency_v,
+                pixel_type,
+            } => Operation::I420ToRgb {
+                dependency_y: dependency(dependency_y),
+                dependency_u: dependency(dependency_u),
+                dependency_v: dependency(dependency_v),
+                pixel_type: *pixel_type,
+            },
+
+            Operation::HConcat {
+                dependency_left,
+                dependency_right,
+            } => Operation::HConcat {
+                dependency_left: dependency(dependency_left),
+                dependency_right: dependency(dependency_right),
+            },
+
+            Operation::VConcat {
+                dependency_top,
+                dependency_bottom,
+            } => Operation::VConcat {
+                dependency_top: dependency(dependency_top),
+                dependency_bottom: dependency(dependency_bottom),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, rc::Rc};
+
+    use interface::Rgb;
+    use macros::map_pixel_kernel;
+
+    use super::deduplicate;
+    use crate::{cdg::Output, new_input, Node};
+
+    #[map_pixel_kernel]
+    fn invert(px: Rgb<u8>) -> Rgb<u8> {
+        interface::Rgb {
+            r: 255 - px.r,
+            g: 255 - px.g,
+            b: 255 - px.b,
+        }
+    }
+
+    #[map_pixel_kernel]
+    fn grey(px: Rgb<u8>) -> Rgb<u8> {
+        interface::Rgb {
+            r: px.g,
+            g: px.g,
+            b: px.g,
+        }
+    }
+
+    fn input() -> Node<Rgb<u8>> {
+        new_input("img".to_string(), 4, 3)
+    }
+
+    // Whether the two outputs are deduplicated into the same node
+    fn merged(a: Node<Rgb<u8>>, b: Node<Rgb<u8>>) -> bool {
+        let (a, b) = (a.into_output(), b.into_output());
+        let outputs = HashMap::from([("a".to_string(), a.node()), ("b".to_string(), b.node())]);
+        let deduplicated = deduplicate(&outputs);
+        let node = |output: &Output| deduplicated[&(output.node() as *const _)].clone();
+        Rc::ptr_eq(&node(&a), &node(&b))
+    }
+
+    #[test]
+    fn equal_chains_are_merged() {
+        let img = input();
+        assert!(merged(
+            img.map_pixel(&invert).flip().crop(1, 1, 2, 2),
+            img.map_pixel(&invert).flip().crop(1, 1, 2, 2)
+        ));
+    }
+
+    #[test]
+    fn equal_inputs_are_merged() {
+        assert!(merged(
+            input().map_pixel(&invert),
+            input().map_pixel(&invert)
+        ));
+    }
+
+    #[test]
+    fn different_kernels_are_not_merged() {
+        let img = input();
+        assert!(!merged(img.map_pixel(&invert), img.map_pixel(&grey)));
+    }
+
+    #[test]
+    fn different_parameters_are_not_merged() {
+        let img = input();
+        assert!(!merged(img.crop(0, 0, 2, 2), img.crop(1, 0, 2, 2)));
+    }
+}
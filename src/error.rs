@@ -0,0 +1,152 @@
+use std::{fmt, io};
+
+use image::ColorType;
+
+use crate::{computational_dependency_graph::ValidationError, pixel::PixelType};
+
+#[derive(Debug)]
+pub enum Error {
+    // The computational dependency graph describes an impossible transformation
+    InvalidGraph(Vec<ValidationError>),
+    // The rustup toolchain the generated source is compiled with cannot be run
+    MissingToolchain {
+        toolchain: &'static str,
+        reason: String,
+    },
+    // rustc rejected the generated source
+    Compile {
+        source: String,
+        stderr: String,
+    },
+    // The block size cannot be used to launch a kernel
+    InvalidBlockSize {
+        block_width: usize,
+        block_height: usize,
+        reason: String,
+    },
+    // The driver could not load the compiled ptx or find the kernel in it
+    #[cfg(feature = "cuda")]
+    PtxLoad(cuda::Error),
+    // The dynamic library compiled by the cpu reference backend could not be loaded
+    LibraryLoad(libloading::Error),
+    // The images given to a call do not match the inputs of the transformation
+    Inputs(Vec<InputError>),
+    #[cfg(feature = "cuda")]
+    Cuda(cuda::Error),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum InputError {
+    // The transformation reads the input, but no image was given for it
+    Missing(String),
+    // An image was given for an input the transformation does not read
+    Unexpected(String),
+    Mismatched {
+        name: String,
+        expected: (usize, usize, PixelType),
+        found: (usize, usize, ColorType),
+    },
+    // A planar frame does not have the size of its format and dimensions
+    FrameSize {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidGraph(errors) => {
+                write!(f, "invalid graph:")?;
+                for error in errors {
+                    write!(f, "\n    {error}")?;
+                }
+                Ok(())
+            }
+            Error::MissingToolchain { toolchain, reason } => write!(
+                f,
+                "the generated source is compiled with the {toolchain} toolchain, which cannot be run ({reason}). \
+                 Install it with `rustup toolchain install {toolchain}`"
+            ),
+            Error::Compile { source: _, stderr } => {
+                write!(f, "compiling generated source failed:\n{stderr}")
+            }
+            Error::InvalidBlockSize {
+                block_width,
+                block_height,
+                reason,
+            } => write!(
+                f,
+                "invalid block size {block_width}x{block_height}: {reason}"
+            ),
+            #[cfg(feature = "cuda")]
+            Error::PtxLoad(error) => write!(f, "loading ptx failed: {error:?}"),
+            Error::LibraryLoad(error) => write!(f, "loading compiled library failed: {error}"),
+            Error::Inputs(errors) => {
+                write!(f, "invalid inputs:")?;
+                for error in errors {
+                    write!(f, "\n    {error}")?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "cuda")]
+            Error::Cuda(error) => write!(f, "cuda error: {error:?}"),
+            Error::Io(error) => write!(f, "io error: {error}"),
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Missing(name) => write!(f, "missing input `{name}`"),
+            InputError::Unexpected(name) => {
+                write!(f, "unexpected input `{name}`, which no output depends on")
+            }
+            InputError::Mismatched {
+                name,
+                expected: (expected_width, expected_height, expected_pixel_type),
+                found: (found_width, found_height, found_color_type),
+            } => write!(
+                f,
+                "input `{name}` should be a {expected_width}x{expected_height} {expected_pixel_type:?} image, \
+                 but a {found_width}x{found_height} {found_color_type:?} image was given"
+            ),
+            InputError::FrameSize {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "frame `{name}` should be {expected} bytes, but {found} bytes were given"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::LibraryLoad(error) => Some(error),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "cuda")]
+impl From<cuda::Error> for Error {
+    fn from(error: cuda::Error) -> Self {
+        Error::Cuda(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::computational_dependency_graph::{toposort, Node, Operation};
+
+// Decides which nodes are computed into their own buffer. Every other node is fused into the kernel of its only consumer,
+// so its pixels are computed where they are needed instead of passing through global memory.
+//
+// A kernel may contain at most one map_patch, whose patches are read from the shared memory tile of the thread block.
+// The map_patch must therefore be evaluated at the position of the thread, i.e. only map_pixel and colour space
+// conversions may be fused on top of it.
+pub fn materialized_nodes(roots: Vec<&Node>) -> HashSet<*const Node> {
+    let roots_set: HashSet<*const Node> = roots.iter().map(|node| *node as *const _).collect();
+    let nodes = toposort(roots);
+
+    let mut consumers: HashMap<*const Node, Vec<&Node>> = HashMap::new();
+    for node in nodes.iter() {
+        for dependency in node.dependencies() {
+            consumers.entry(dependency).or_default().push(node);
+        }
+    }
+
+    let mut materialized = HashSet::new();
+    // the nodes whose kernel, including the nodes fused into it, contains a map_patch
+    let mut contains_patch: HashSet<*const Node> = HashSet::new();
+
+    for node in nodes {
+        let ptr = node as *const Node;
+        let Node::Operation(operation) = node else {
+            materialized.insert(ptr);
+            continue;
+        };
+
+        let patch = match operation {
+            Operation::MapPatch {
+                dependency: _,
+                f: _,
+                patch_width: _,
+                patch_height: _,
+                pixel_type: _,
+                border: _,
+            } => true,
+
+            Operation::MapPixel {
+                dependency,
+                f: _,
+                pixel_type: _,
+            }
+            | Operation::ConvertColourSpace {
+                dependency,
+                pixel_type: _,
+            } => {
+                let dependency = &**dependency as *const Node;
+                !materialized.contains(&dependency) && contains_patch.contains(&dependency)
+            }
+
+            _ => false,
+        };
+        if patch {
+            contains_patch.insert(ptr);
+        }
+
+        let fusable = !roots_set.contains(&ptr)
+            && match consumers.get(&ptr).map(Vec::as_slice) {
+                Some([Node::Operation(consumer)]) => match consumer {
+                    Operation::MapPixel {
+                        dependency: _,
+                        f: _,
+                        pixel_type: _,
+                    }
+                    | Operation::ConvertColourSpace {
+                        dependency: _,
+                        pixel_type: _,
+                    } => true,
+
+                    // these read their dependency at other positions than their own
+                    Operation::MapPatch {
+                        dependency: _,
+                        f: _,
+                        patch_width: _,
+                        patch_height: _,
+                        pixel_type: _,
+                        border: _,
+                    }
+                    | Operation::Flip { dependency: _ }
+                    | Operation::Crop {
+                        dependency: _,
+                        x: _,
+                        y: _,
+                        width: _,
+                        height: _,
+                    }
+                    | Operation::Pad {
+                        dependency: _,
+                        left: _,
+                        right: _,
+                        top: _,
+                        bottom: _,
+                        border: _,
+                    }
+                    | Operation::Resize {
+                        dependency: _,
+                        width: _,
+                        height: _,
+                        filter: _,
+                    }
+                    | Operation::Nv12ToRgb {
+                        dependency_y: _,
+                        dependency_uv: _,
+                        pixel_type: _,
+                    }
+                    | Operation::I420ToRgb {
+                        dependency_y: _,
+                        dependency_u: _,
+                        dependency_v: _,
+                        pixel_type: _,
+                    }
+                    | Operation::HConcat {
+                        dependency_left: _,
+                        dependency_right: _,
+                    }
+                    | Operation::VConcat {
+                        dependency_top: _,
+                        dependency_bottom: _,
+                    } => !patch,
+
+                    // the kernel is handed its dependency as an image, which must be in memory
+                    Operation::MapImage {
+                        dependency: _,
+                        f: _,
+                        height: _,
+                        width: _,
+                        pixel_type: _,
+                    } => false,
+                },
+                _ => false,
+            };
+
+        if !fusable {
+            materialized.insert(ptr);
+        }
+    }
+
+    materialized
+}
+
+// Returns the node and the nodes fused into its kernel
+pub fn kernel_nodes<'a>(node: &'a Node, materialized: &HashSet<*const Node>) -> Vec<&'a Node> {
+    let mut result = vec![node];
+    let mut stack = node.dependencies();
+
+    while let Some(dependency) = stack.pop() {
+        if !materialized.contains(&(dependency as *const _)) {
+            stack.extend(dependency.dependencies());
+            result.push(dependency);
+        }
+    }
+    result
+}
+
+// Returns the materialized nodes read by the kernel computing the node, looking through the nodes fused into it
+pub fn kernel_dependencies<'a>(
+    node: &'a Node,
+    materialized: &HashSet<*const Node>,
+) -> Vec<&'a Node> {
+    let mut result: Vec<&Node> = Vec::new();
+    let mut stack = node.dependencies();
+
+    while let Some(dependency) = stack.pop() {
+        if !materialized.contains(&(dependency as *const _)) {
+            stack.extend(dependency.dependencies());
+        } else if !result.iter().any(|node| std::ptr::eq(*node, dependency)) {
+            result.push(dependency);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, rc::Rc};
+
+    use itertools::Itertools;
+    use syn::parse_quote;
+
+    use super::{kernel_dependencies, kernel_nodes, materialized_nodes};
+    use crate::{
+        border::Border,
+        computational_dependency_graph::{Node, Operation},
+        pixel::PixelType,
+        resize::ResizeFilter,
+    };
+
+    fn input(name: &str) -> Rc<Node> {
+        Rc::new(Node::Input {
+            name: name.to_string(),
+            width: 8,
+            height: 8,
+            pixel_type: PixelType::RgbU8,
+        })
+    }
+
+    fn operation(operation: Operation) -> Rc<Node> {
+        Rc::new(Node::Operation(operation))
+    }
+
+    fn map_pixel(dependency: &Rc<Node>) -> Rc<Node> {
+        operation(Operation::MapPixel {
+            dependency: dependency.clone(),
+            f: parse_quote! { fn f(px: Rgb<u8>) -> Rgb<u8> { px } },
+            pixel_type: PixelType::RgbU8,
+        })
+    }
+
+    fn map_patch(dependency: &Rc<Node>) -> Rc<Node> {
+        operation(Operation::MapPatch {
+            dependency: dependency.clone(),
+            f: parse_quote! { fn f(patch: Patch<3, 3, Rgb<u8>>) -> Rgb<u8> { patch.center() } },
+            patch_width: 3,
+            patch_height: 3,
+            pixel_type: PixelType::RgbU8,
+            border: Border::Replicate,
+        })
+    }
+
+    fn map_image(dependency: &Rc<Node>) -> Rc<Node> {
+        operation(Operation::MapImage {
+            dependency: dependency.clone(),
+            f: parse_quote! { fn f(image: &Image<Rgb<u8>>, col: usize, row: usize) -> Rgb<u8> { image[(col, row)] } },
+            width: 8,
+            height: 8,
+            pixel_type: PixelType::RgbU8,
+        })
+    }
+
+    fn to_hsv(dependency: &Rc<Node>) -> Rc<Node> {
+        operation(Operation::ConvertColourSpace {
+            dependency: dependency.clone(),
+            pixel_type: PixelType::HsvF32,
+        })
+    }
+
+    // Every consumer reading its dependency at other positions than its own
+    fn displacing_consumers(dependency: &Rc<Node>) -> Vec<(&'static str, Rc<Node>)> {
+        vec![
+            ("map_patch", map_patch(dependency)),
+            (
+                "flip",
+                operation(Operation::Flip {
+                    dependency: dependency.clone(),
+                }),
+            ),
+            (
+                "crop",
+                operation(Operation::Crop {
+                    dependency: dependency.clone(),
+                    x: 1,
+                    y: 1,
+                    width: 4,
+                    height: 4,
+                }),
+            ),
+            (
+                "pad",
+                operation(Operation::Pad {
+                    dependency: dependency.clone(),
+                    left: 1,
+                    right: 1,
+                    top: 1,
+                    bottom: 1,
+                    border: Border::Replicate,
+                }),
+            ),
+            (
+                "resize",
+                operation(Operation::Resize {
+                    dependency: dependency.clone(),
+                    width: 4,
+                    height: 4,
+                    filter: ResizeFilter::Bilinear,
+                }),
+            ),
+            (
+                "nv12_to_rgb",
+                operation(Operation::Nv12ToRgb {
+                    dependency_y: dependency.clone(),
+                    dependency_uv: input("uv"),
+                    pixel_type: PixelType::RgbU8,
+                }),
+            ),
+            (
+                "i420_to_rgb",
+                operation(Operation::I420ToRgb {
+                    dependency_y: dependency.clone(),
+                    dependency_u: input("u"),
+                    dependency_v: input("v"),
+                    pixel_type: PixelType::RgbU8,
+                }),
+            ),
+            (
+                "h_concat",
+                operation(Operation::HConcat {
+                    dependency_left: dependency.clone(),
+                    dependency_right: input("right"),
+                }),
+            ),
+            (
+                "v_concat",
+                operation(Operation::VConcat {
+                    dependency_top: dependency.clone(),
+                    dependency_bottom: input("bottom"),
+                }),
+            ),
+        ]
+    }
+
+    fn materialized(roots: &[&Rc<Node>]) -> HashSet<*const Node> {
+        materialized_nodes(roots.iter().map(|node| &***node).collect())
+    }
+
+    fn ptrs(nodes: &[&Rc<Node>]) -> HashSet<*const Node> {
+        nodes.iter().map(|node| Rc::as_ptr(node)).collect()
+    }
+
+    #[test]
+    fn single_consumer_chain_is_fused() {
+        let img = input("img");
+        let first = map_pixel(&img);
+        let second = to_hsv(&first);
+        let out = map_pixel(&second);
+        assert_eq!(materialized(&[&out]), ptrs(&[&img, &out]));
+    }
+
+    #[test]
+    fn shared_node_is_materialized() {
+        let img = input("img");
+        let shared = map_pixel(&img);
+        let left = map_pixel(&shared);
+        let right = map_pixel(&shared);
+        assert_eq!(
+            materialized(&[&left, &right]),
+            ptrs(&[&img, &shared, &left, &right])
+        );
+    }
+
+    #[test]
+    fn roots_are_materialized() {
+        let img = input("img");
+        let inner = map_pixel(&img);
+        let out = map_pixel(&inner);
+        assert_eq!(materialized(&[&inner, &out]), ptrs(&[&img, &inner, &out]));
+    }
+
+    #[test]
+    fn pixelwise_producer_fuses_into_every_displacing_consumer() {
+        let producer = map_pixel(&input("img"));
+        for (name, consumer) in displacing_consumers(&producer) {
+            let materialized = materialized(&[&consumer]);
+            assert!(!materialized.contains(&Rc::as_ptr(&producer)), "{name}");
+        }
+    }
+
+    #[test]
+    fn patch_producer_does_not_fuse_into_displacing_consumers() {
+        let producer = map_patch(&input("img"));
+        for (name, consumer) in displacing_consumers(&producer) {
+            let materialized = materialized(&[&consumer]);
+            assert!(materialized.contains(&Rc::as_ptr(&producer)), "{name}");
+        }
+    }
+
+    #[test]
+    fn patch_propagates_through_pixelwise_consumers() {
+        let img = input("img");
+        let patch = map_patch(&img);
+        let pixel = map_pixel(&patch);
+        let hsv = to_hsv(&pixel);
+        let flip = operation(Operation::Flip {
+            dependency: hsv.clone(),
+        });
+        assert_eq!(materialized(&[&flip]), ptrs(&[&img, &hsv, &flip]));
+    }
+
+    #[test]
+    fn nothing_fuses_into_map_image() {
+        let img = input("img");
+        let pixel = map_pixel(&img);
+        let out = map_image(&pixel);
+        assert_eq!(materialized(&[&out]), ptrs(&[&img, &pixel, &out]));
+    }
+
+    #[test]
+    fn kernel_nodes_and_dependencies() {
+        let img = input("img");
+        let shared = map_pixel(&img);
+        let left = map_pixel(&shared);
+        let right = map_pixel(&shared);
+        let right_inverted = map_pixel(&right);
+        let concat = operation(Operation::HConcat {
+            dependency_left: left.clone(),
+            dependency_right: right_inverted.clone(),
+        });
+        let out = map_pixel(&concat);
+        let materialized = materialized(&[&out, &right]);
+
+        let as_ptrs = |nodes: Vec<&Node>| {
+            nodes
+                .into_iter()
+                .map(|node| node as *const Node)
+                .collect_vec()
+        };
+        assert_eq!(
+            as_ptrs(kernel_nodes(&out, &materialized))
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            ptrs(&[&out, &concat, &left, &right_inverted])
+        );
+        // the materialized frontier of the kernel, without the input behind it
+        let dependencies = as_ptrs(kernel_dependencies(&out, &materialized));
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(
+            dependencies.into_iter().collect::<HashSet<_>>(),
+            ptrs(&[&shared, &right])
+        );
+    }
+
+    #[test]
+    fn kernel_dependencies_are_unique() {
+        let img = input("img");
+        let concat = operation(Operation::VConcat {
+            dependency_top: map_pixel(&img),
+            dependency_bottom: map_pixel(&img),
+        });
+        let materialized = materialized(&[&concat]);
+        let dependencies = kernel_dependencies(&concat, &materialized);
+        assert_eq!(dependencies.len(), 1);
+        assert!(std::ptr::eq(dependencies[0], &*img));
+    }
+}
@@ -1,26 +1,45 @@
 use std::{marker::PhantomData, rc::Rc};
 
-use interface::{Image, Patch};
+use interface::{Channel, Hsv, Image, Interpolate, Lab, Patch, Rgb, YCbCr};
 use kernel::{MapImageKernel, MapPatchKernel, MapPixelKernel};
 
+mod border;
+mod buffer;
 mod codegen;
 mod compiler;
 mod computational_dependency_graph;
+mod cpu;
+mod deduplication;
+mod error;
+#[cfg(feature = "cuda")]
+mod fusion;
 mod pixel;
+mod planar;
+mod resize;
+#[cfg(feature = "cuda")]
 mod transformation;
 
-use cdg::Operation;
+pub use border::Border;
+pub use buffer::RequiredInput;
+pub use cdg::ValidationError;
+use cdg::{Operation, Output};
+#[cfg(feature = "cuda")]
+pub use codegen::LaunchGeometry;
 use computational_dependency_graph as cdg;
-use pixel::Pixel;
-use transformation::Output;
-pub use transformation::Transformation;
+pub use cpu::CpuTransformation;
+pub use error::{Error, InputError, Result};
+pub use pixel::{CustomPixelType, ImagePixel, Pixel, PixelType};
+pub use planar::{new_planar_input, PlanarFormat, PlanarInput};
+pub use resize::ResizeFilter;
+#[cfg(feature = "cuda")]
+pub use transformation::{Transformation, TransformationBuilder};
 
 pub struct Node<P> {
     p: PhantomData<P>,
     inner: Rc<cdg::Node>,
 }
 
-pub fn new_input<P: Pixel>(name: String, width: usize, height: usize) -> Node<P> {
+pub fn new_input<P: ImagePixel>(name: String, width: usize, height: usize) -> Node<P> {
     Node {
         p: PhantomData,
         inner: Rc::new(cdg::Node::Input {
@@ -46,6 +65,56 @@ impl<P: Pixel> Node<P> {
         })
     }
 
+    // The width x height rectangle whose top left corner is at (x, y)
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self::new(cdg::Operation::Crop {
+            dependency: self.inner.clone(),
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    // Extends the image by the given number of pixels on each side
+    pub fn pad(
+        &self,
+        left: usize,
+        right: usize,
+        top: usize,
+        bottom: usize,
+        border: Border<P>,
+    ) -> Self {
+        Self::new(cdg::Operation::Pad {
+            dependency: self.inner.clone(),
+            left,
+            right,
+            top,
+            bottom,
+            border: border.into_bytes(),
+        })
+    }
+
+    // Scales the image to width x height. Only the built-in pixel types can be interpolated.
+    pub fn resize(&self, width: usize, height: usize, filter: ResizeFilter) -> Self
+    where
+        P: Interpolate,
+    {
+        Self::new(Operation::Resize {
+            dependency: self.inner.clone(),
+            width,
+            height,
+            filter,
+        })
+    }
+
+    fn convert_colour_space<T: Pixel>(&self) -> Node<T> {
+        Self::new(Operation::ConvertColourSpace {
+            dependency: self.inner.clone(),
+            pixel_type: T::ty(),
+        })
+    }
+
     pub fn map_pixel<T: Pixel>(&self, kernel: &MapPixelKernel<P, T>) -> Node<T> {
         let f =
             syn::parse_str(kernel.src()).expect("kernel.src should be parseable as syn::ItemFn");
@@ -57,9 +126,28 @@ impl<P: Pixel> Node<P> {
         })
     }
 
-    pub fn map_patch<const N: usize, T: Pixel>(
+    // Computes every pixel from the patch centred on it, which is always centred since patches have no anchor. Neighbours
+    // outside of the image are zero.
+    pub fn map_patch<const W: usize, const H: usize, T: Pixel>(
+        &self,
+        kernel: &MapPatchKernel<Patch<W, H, P>, T>,
+    ) -> Node<T> {
+        let zero = Border::Constant(vec![0; P::ty().layout().size()]);
+        self.map_patch_raw(kernel, zero)
+    }
+
+    pub fn map_patch_with_border<const W: usize, const H: usize, T: Pixel>(
+        &self,
+        kernel: &MapPatchKernel<Patch<W, H, P>, T>,
+        border: Border<P>,
+    ) -> Node<T> {
+        self.map_patch_raw(kernel, border.into_bytes())
+    }
+
+    fn map_patch_raw<const W: usize, const H: usize, T: Pixel>(
         &self,
-        kernel: &MapPatchKernel<Patch<N, P>, T>,
+        kernel: &MapPatchKernel<Patch<W, H, P>, T>,
+        border: Border<Vec<u8>>,
     ) -> Node<T> {
         let f =
             syn::parse_str(kernel.src()).expect("kernel.src should be parseable as syn::ItemFn");
@@ -67,8 +155,10 @@ impl<P: Pixel> Node<P> {
         Self::new(Operation::MapPatch {
             dependency: self.inner.clone(),
             f,
-            dimension: N,
+            patch_width: W,
+            patch_height: H,
             pixel_type: T::ty(),
+            border,
         })
     }
 
@@ -104,7 +194,55 @@ impl<P: Pixel> Node<P> {
         })
     }
 
-    pub fn into_output(self) -> Output {
+    pub fn into_output(self) -> Output
+    where
+        P: ImagePixel,
+    {
         Output::new(self.inner)
     }
 }
+
+// Rgb pixels are taken to be sRGB encoded. See interface for the ranges of the colour spaces.
+impl<T: Channel> Node<Rgb<T>>
+where
+    Rgb<T>: Pixel,
+{
+    pub fn to_hsv(&self) -> Node<Hsv<f32>> {
+        self.convert_colour_space()
+    }
+
+    pub fn to_ycbcr(&self) -> Node<YCbCr<f32>> {
+        self.convert_colour_space()
+    }
+
+    pub fn to_lab(&self) -> Node<Lab<f32>> {
+        self.convert_colour_space()
+    }
+}
+
+impl Node<Hsv<f32>> {
+    pub fn to_rgb<T: Channel>(&self) -> Node<Rgb<T>>
+    where
+        Rgb<T>: Pixel,
+    {
+        self.convert_colour_space()
+    }
+}
+
+impl Node<YCbCr<f32>> {
+    pub fn to_rgb<T: Channel>(&self) -> Node<Rgb<T>>
+    where
+        Rgb<T>: Pixel,
+    {
+        self.convert_colour_space()
+    }
+}
+
+impl Node<Lab<f32>> {
+    pub fn to_rgb<T: Channel>(&self) -> Node<Rgb<T>>
+    where
+        Rgb<T>: Pixel,
+    {
+        self.convert_colour_space()
+    }
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, fs, path::Path};
 
 use cuda::Cuda;
-use cuda_fusion::{new_input, Transformation};
+use cuda_fusion::{new_input, Border, Transformation};
 use interface::{Image, Patch, Rgb};
 use macros::{map_image_kernel, map_patch_kernel, map_pixel_kernel};
 
@@ -16,15 +16,12 @@ fn to_f32(px: Rgb<u8>) -> Rgb<f32> {
 }
 
 #[map_patch_kernel]
-fn convolve(patch: Patch<3, Rgb<f32>>) -> Rgb<f32> {
+fn convolve(patch: Patch<3, 3, Rgb<f32>>) -> Rgb<f32> {
     let m = [[0.1, 0.2, 0.1], [-0.1, 0.5, -0.1], [0.1, 0.2, 0.1]];
     let mut px = Default::default();
 
-    for r in 0..3 {
-        for c in 0..3 {
-            let w = m[r][c];
-            px += patch.get(c, r) * w;
-        }
+    for (dx, dy, neighbour) in patch.iter() {
+        px += neighbour * m[(dy + 1) as usize][(dx + 1) as usize];
     }
     px
 }
@@ -49,7 +46,7 @@ fn main() {
     let res = a.map_image(&shroom_filter, width, height);
     let res2 = a
         .map_pixel(&to_f32)
-        .map_patch(&convolve)
+        .map_patch_with_border(&convolve, Border::Replicate)
         .map_pixel(&to_u8)
         .flip();
     let res3 = res.h_concat(&res2);
@@ -82,11 +79,5 @@ fn main() {
 }
 
 fn load_image<P: AsRef<Path>>(path: P) -> image::DynamicImage {
-    image::DynamicImage::ImageRgb8(
-        image::io::Reader::open(path)
-            .unwrap()
-            .decode()
-            .unwrap()
-            .to_rgb8(),
-    )
+    image::DynamicImage::ImageRgb8(image::open(path).unwrap().to_rgb8())
 }
@@ -1,20 +1,74 @@
-use std::alloc::Layout;
+use std::{alloc::Layout, fmt};
 
-use interface::{Rgb, SharedMemory};
-use quote::quote;
+use image::ColorType;
+use interface::{CbCr, Hsv, Lab, Luma, Rgb, Rgba, SharedMemory, YCbCr};
 use quote::ToTokens;
+use quote::{format_ident, quote};
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum PixelType {
     RgbU8,
+    RgbU16,
     RgbF32,
+    RgbaU8,
+    RgbaF32,
+    LumaU8,
+    LumaU16,
+    /// Single channel f32 images, which the image crate has no color type for. Inputs and outputs of this pixel type are
+    /// therefore Rgb32F images: inputs are converted to their luma with `DynamicImage::to_luma32f`, and outputs are
+    /// written with the luma in each of the three channels.
+    LumaF32,
+    CbCrU8,
+    HsvF32,
+    YCbCrF32,
+    LabF32,
+    Custom(CustomPixelType),
 }
 
 impl PixelType {
     pub const fn layout(&self) -> Layout {
         match self {
             PixelType::RgbU8 => Layout::new::<Rgb<u8>>(),
+            PixelType::RgbU16 => Layout::new::<Rgb<u16>>(),
             PixelType::RgbF32 => Layout::new::<Rgb<f32>>(),
+            PixelType::RgbaU8 => Layout::new::<Rgba<u8>>(),
+            PixelType::RgbaF32 => Layout::new::<Rgba<f32>>(),
+            PixelType::LumaU8 => Layout::new::<Luma<u8>>(),
+            PixelType::LumaU16 => Layout::new::<Luma<u16>>(),
+            PixelType::LumaF32 => Layout::new::<Luma<f32>>(),
+            PixelType::CbCrU8 => Layout::new::<CbCr<u8>>(),
+            PixelType::HsvF32 => Layout::new::<Hsv<f32>>(),
+            PixelType::YCbCrF32 => Layout::new::<YCbCr<f32>>(),
+            PixelType::LabF32 => Layout::new::<Lab<f32>>(),
+            PixelType::Custom(custom) => custom.layout,
+        }
+    }
+
+    // The image crate has no single channel f32 images, so LumaF32 images are converted to and from Rgb32F
+    pub const fn color_type(&self) -> ColorType {
+        match self {
+            PixelType::RgbU8 => ColorType::Rgb8,
+            PixelType::RgbU16 => ColorType::Rgb16,
+            PixelType::RgbF32 => ColorType::Rgb32F,
+            PixelType::RgbaU8 => ColorType::Rgba8,
+            PixelType::RgbaF32 => ColorType::Rgba32F,
+            PixelType::LumaU8 => ColorType::L8,
+            PixelType::LumaU16 => ColorType::L16,
+            PixelType::LumaF32 => ColorType::Rgb32F,
+            PixelType::CbCrU8 => ColorType::La8,
+            PixelType::HsvF32 | PixelType::YCbCrF32 | PixelType::LabF32 => {
+                panic!("colour space pixel types have no image crate color type")
+            }
+            PixelType::Custom(_) => panic!("custom pixel types have no image crate color type"),
+        }
+    }
+
+    // An expression of the pixel with the given bytes
+    pub(crate) fn constant(&self, bytes: &[u8]) -> proc_macro2::TokenStream {
+        assert_eq!(bytes.len(), self.layout().size());
+        let size = bytes.len();
+        quote! {
+            unsafe { core::mem::transmute::<[u8; #size], #self>([#(#bytes),*]) }
         }
     }
 }
@@ -23,25 +77,105 @@ impl ToTokens for PixelType {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         tokens.extend(match self {
             PixelType::RgbU8 => quote! {interface::Rgb<u8>},
+            PixelType::RgbU16 => quote! {interface::Rgb<u16>},
             PixelType::RgbF32 => quote! {interface::Rgb<f32>},
+            PixelType::RgbaU8 => quote! {interface::Rgba<u8>},
+            PixelType::RgbaF32 => quote! {interface::Rgba<f32>},
+            PixelType::LumaU8 => quote! {interface::Luma<u8>},
+            PixelType::LumaU16 => quote! {interface::Luma<u16>},
+            PixelType::LumaF32 => quote! {interface::Luma<f32>},
+            PixelType::CbCrU8 => quote! {interface::CbCr<u8>},
+            PixelType::HsvF32 => quote! {interface::Hsv<f32>},
+            PixelType::YCbCrF32 => quote! {interface::YCbCr<f32>},
+            PixelType::LabF32 => quote! {interface::Lab<f32>},
+            PixelType::Custom(custom) => {
+                let name = format_ident!("{}", custom.name);
+                quote! {#name}
+            }
         });
     }
 }
 
+// A pixel struct implementing Pixel with #[derive(Pixel)]. Kernels refer to it by its name, so its definition is emitted
+// into the generated code of every kernel using it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CustomPixelType {
+    name: &'static str,
+    src: &'static str,
+    layout: Layout,
+}
+
+impl CustomPixelType {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, src: &'static str, layout: Layout) -> Self {
+        Self { name, src, layout }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // The struct and its SharedMemory impl, as written by the derive macro
+    pub(crate) fn definition(&self) -> Vec<syn::Item> {
+        syn::parse_str::<syn::File>(self.src)
+            .expect("the definition of a custom pixel type should be parseable as syn::File")
+            .items
+    }
+}
+
+// Only the name is printed, since the definition is rarely of interest in error messages
+impl fmt::Debug for CustomPixelType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// A pixel the nodes of a transformation can be made of.
+///
+/// # Safety
+///
+/// The type must be a `#[repr(C)]` struct of the scalars SharedMemory is implemented for, with the layout `ty` describes,
+/// since pixels are copied between the host and the device as bytes. Implement it with `#[derive(Pixel)]`.
 pub unsafe trait Pixel: SharedMemory {
+    fn ty() -> PixelType;
+}
+
+// Pixels with a counterpart in the image crate, which are the only ones inputs and outputs can be made of
+pub trait ImagePixel: Pixel {
     type ImageCratePixel: image::Pixel;
 
-    fn ty() -> PixelType;
+    #[allow(clippy::wrong_self_convention)]
     fn into_image_crate_pixel(&self) -> Self::ImageCratePixel;
     fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self;
 }
 
 unsafe impl Pixel for Rgb<u8> {
+    fn ty() -> PixelType {
+        PixelType::RgbU8
+    }
+}
+
+impl ImagePixel for Rgb<u8> {
     type ImageCratePixel = image::Rgb<u8>;
 
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::Rgb([self.r, self.g, self.b])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::Rgb([r, g, b]) = pixel;
+        Self { r, g, b }
+    }
+}
+
+unsafe impl Pixel for Rgb<u16> {
     fn ty() -> PixelType {
-        PixelType::RgbU8
+        PixelType::RgbU16
     }
+}
+
+impl ImagePixel for Rgb<u16> {
+    type ImageCratePixel = image::Rgb<u16>;
 
     fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
         image::Rgb([self.r, self.g, self.b])
@@ -54,11 +188,13 @@ unsafe impl Pixel for Rgb<u8> {
 }
 
 unsafe impl Pixel for Rgb<f32> {
-    type ImageCratePixel = image::Rgb<f32>;
-
     fn ty() -> PixelType {
         PixelType::RgbF32
     }
+}
+
+impl ImagePixel for Rgb<f32> {
+    type ImageCratePixel = image::Rgb<f32>;
 
     fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
         image::Rgb([self.r, self.g, self.b])
@@ -69,3 +205,140 @@ unsafe impl Pixel for Rgb<f32> {
         Self { r, g, b }
     }
 }
+
+unsafe impl Pixel for Rgba<u8> {
+    fn ty() -> PixelType {
+        PixelType::RgbaU8
+    }
+}
+
+impl ImagePixel for Rgba<u8> {
+    type ImageCratePixel = image::Rgba<u8>;
+
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::Rgba([self.r, self.g, self.b, self.a])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::Rgba([r, g, b, a]) = pixel;
+        Self { r, g, b, a }
+    }
+}
+
+unsafe impl Pixel for Rgba<f32> {
+    fn ty() -> PixelType {
+        PixelType::RgbaF32
+    }
+}
+
+impl ImagePixel for Rgba<f32> {
+    type ImageCratePixel = image::Rgba<f32>;
+
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::Rgba([self.r, self.g, self.b, self.a])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::Rgba([r, g, b, a]) = pixel;
+        Self { r, g, b, a }
+    }
+}
+
+unsafe impl Pixel for Luma<u8> {
+    fn ty() -> PixelType {
+        PixelType::LumaU8
+    }
+}
+
+impl ImagePixel for Luma<u8> {
+    type ImageCratePixel = image::Luma<u8>;
+
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::Luma([self.l])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::Luma([l]) = pixel;
+        Self { l }
+    }
+}
+
+unsafe impl Pixel for Luma<u16> {
+    fn ty() -> PixelType {
+        PixelType::LumaU16
+    }
+}
+
+impl ImagePixel for Luma<u16> {
+    type ImageCratePixel = image::Luma<u16>;
+
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::Luma([self.l])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::Luma([l]) = pixel;
+        Self { l }
+    }
+}
+
+// Inputs and outputs of Luma<f32> are Rgb32F images, see PixelType::LumaF32
+unsafe impl Pixel for Luma<f32> {
+    fn ty() -> PixelType {
+        PixelType::LumaF32
+    }
+}
+
+impl ImagePixel for Luma<f32> {
+    type ImageCratePixel = image::Luma<f32>;
+
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::Luma([self.l])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::Luma([l]) = pixel;
+        Self { l }
+    }
+}
+
+// The chroma plane of NV12 frames is given as a two channel image
+unsafe impl Pixel for CbCr<u8> {
+    fn ty() -> PixelType {
+        PixelType::CbCrU8
+    }
+}
+
+impl ImagePixel for CbCr<u8> {
+    type ImageCratePixel = image::LumaA<u8>;
+
+    fn into_image_crate_pixel(&self) -> Self::ImageCratePixel {
+        image::LumaA([self.cb, self.cr])
+    }
+
+    fn from_image_crate_pixel(pixel: Self::ImageCratePixel) -> Self {
+        let image::LumaA([cb, cr]) = pixel;
+        Self { cb, cr }
+    }
+}
+
+// The colour spaces are only computed in, so they have no counterpart in the image crate and must be converted back to
+// Rgb before being output
+
+unsafe impl Pixel for Hsv<f32> {
+    fn ty() -> PixelType {
+        PixelType::HsvF32
+    }
+}
+
+unsafe impl Pixel for YCbCr<f32> {
+    fn ty() -> PixelType {
+        PixelType::YCbCrF32
+    }
+}
+
+unsafe impl Pixel for Lab<f32> {
+    fn ty() -> PixelType {
+        PixelType::LabF32
+    }
+}
@@ -0,0 +1,292 @@
+use std::{collections::HashMap, rc::Rc};
+
+use image::{DynamicImage, GrayAlphaImage, GrayImage};
+use interface::{Channel, Rgb};
+
+use crate::{
+    computational_dependency_graph as cdg, new_input, Error, InputError, Node, Pixel, Result,
+};
+use cdg::Operation;
+
+// Layouts of 8 bit video frames with a full resolution luma plane followed by chroma planes subsampled by two in both
+// directions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PlanarFormat {
+    // One plane of interleaved cb and cr
+    Nv12,
+    // A plane of cb followed by a plane of cr
+    I420,
+}
+
+impl PlanarFormat {
+    // The size in bytes of a tightly packed frame
+    pub fn frame_size(&self, width: usize, height: usize) -> usize {
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        width * height + 2 * chroma_width * chroma_height
+    }
+
+    // Splits a tightly packed frame into the images of its planes, keyed by the names of their inputs, to be given to
+    // a call along with the other inputs
+    pub fn planes(
+        &self,
+        name: &str,
+        width: usize,
+        height: usize,
+        frame: &[u8],
+    ) -> Result<HashMap<String, DynamicImage>> {
+        let expected = self.frame_size(width, height);
+        if frame.len() != expected {
+            return Err(Error::Inputs(vec![InputError::FrameSize {
+                name: name.to_string(),
+                expected,
+                found: frame.len(),
+            }]));
+        }
+
+        let luma = |width: usize, height: usize, plane: &[u8]| {
+            DynamicImage::ImageLuma8(
+                GrayImage::from_raw(width as u32, height as u32, plane.to_vec())
+                    .expect("the plane should have the size of the image"),
+            )
+        };
+
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        let (y, chroma) = frame.split_at(width * height);
+        let mut planes = HashMap::from([(plane_name(name, "y"), luma(width, height, y))]);
+
+        match self {
+            PlanarFormat::Nv12 => {
+                let uv = GrayAlphaImage::from_raw(
+                    chroma_width as u32,
+                    chroma_height as u32,
+                    chroma.to_vec(),
+                )
+                .expect("the plane should have the size of the image");
+                planes.insert(plane_name(name, "uv"), DynamicImage::ImageLumaA8(uv));
+            }
+            PlanarFormat::I420 => {
+                let (u, v) = chroma.split_at(chroma_width * chroma_height);
+                planes.insert(plane_name(name, "u"), luma(chroma_width, chroma_height, u));
+                planes.insert(plane_name(name, "v"), luma(chroma_width, chroma_height, v));
+            }
+        }
+
+        Ok(planes)
+    }
+}
+
+fn chroma_size(width: usize, height: usize) -> (usize, usize) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+fn plane_name(name: &str, plane: &str) -> String {
+    format!("{name}.{plane}")
+}
+
+// A frame whose planes are separate inputs, named after the frame and the plane, e.g. `camera.y` and `camera.uv`. Each
+// plane is copied to the device by its own memcpy node.
+pub struct PlanarInput(Planes);
+
+enum Planes {
+    Nv12 {
+        y: Rc<cdg::Node>,
+        uv: Rc<cdg::Node>,
+    },
+    I420 {
+        y: Rc<cdg::Node>,
+        u: Rc<cdg::Node>,
+        v: Rc<cdg::Node>,
+    },
+}
+
+pub fn new_planar_input(
+    name: String,
+    format: PlanarFormat,
+    width: usize,
+    height: usize,
+) -> PlanarInput {
+    let (chroma_width, chroma_height) = chroma_size(width, height);
+    let y = new_input::<interface::Luma<u8>>(plane_name(&name, "y"), width, height).inner;
+
+    PlanarInput(match format {
+        PlanarFormat::Nv12 => Planes::Nv12 {
+            y,
+            uv: new_input::<interface::CbCr<u8>>(
+                plane_name(&name, "uv"),
+                chroma_width,
+                chroma_height,
+            )
+            .inner,
+        },
+        PlanarFormat::I420 => Planes::I420 {
+            y,
+            u: new_input::<interface::Luma<u8>>(
+                plane_name(&name, "u"),
+                chroma_width,
+                chroma_height,
+            )
+            .inner,
+            v: new_input::<interface::Luma<u8>>(
+                plane_name(&name, "v"),
+                chroma_width,
+                chroma_height,
+            )
+            .inner,
+        },
+    })
+}
+
+impl PlanarInput {
+    // Converts the frame from BT.601 video range YCbCr to sRGB
+    pub fn to_rgb<T: Channel>(&self) -> Node<Rgb<T>>
+    where
+        Rgb<T>: Pixel,
+    {
+        let pixel_type = Rgb::<T>::ty();
+        Node::<Rgb<T>>::new(match &self.0 {
+            Planes::Nv12 { y, uv } => Operation::Nv12ToRgb {
+                dependency_y: y.clone(),
+                dependency_uv: uv.clone(),
+                pixel_type,
+            },
+            Planes::I420 { y, u, v } => Operation::I420ToRgb {
+                dependency_y: y.clone(),
+                dependency_u: u.clone(),
+                dependency_v: v.clone(),
+                pixel_type,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::{DynamicImage, RgbImage};
+    use interface::{FromPixel, YCbCr};
+
+    use super::{new_planar_input, PlanarFormat};
+    use crate::{CpuTransformation, Error, InputError};
+
+    // 3x3, so the chroma planes are 2x2 and the last chroma column and row cover a single pixel
+    const WIDTH: usize = 3;
+    const HEIGHT: usize = 3;
+
+    const Y: [u8; 9] = [16, 235, 100, 50, 128, 200, 0, 255, 180];
+    const CB: [u8; 4] = [128, 16, 240, 90];
+    const CR: [u8; 4] = [128, 240, 16, 170];
+
+    fn nv12() -> Vec<u8> {
+        let uv = CB.iter().zip(CR).flat_map(|(cb, cr)| [*cb, cr]);
+        Y.into_iter().chain(uv).collect()
+    }
+
+    fn i420() -> Vec<u8> {
+        Y.into_iter().chain(CB).chain(CR).collect()
+    }
+
+    fn raw(image: &DynamicImage) -> Vec<u8> {
+        image.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn frame_size() {
+        for format in [PlanarFormat::Nv12, PlanarFormat::I420] {
+            assert_eq!(format.frame_size(4, 2), 8 + 2 * 2);
+            assert_eq!(format.frame_size(3, 3), 9 + 2 * 4);
+            assert_eq!(format.frame_size(1920, 1080), 1920 * 1080 * 3 / 2);
+        }
+    }
+
+    #[test]
+    fn nv12_planes() {
+        let planes = PlanarFormat::Nv12
+            .planes("cam", WIDTH, HEIGHT, &nv12())
+            .unwrap();
+        assert_eq!(planes.len(), 2);
+
+        let y = &planes["cam.y"];
+        assert_eq!((y.width(), y.height()), (3, 3));
+        assert_eq!(raw(y), Y);
+
+        let uv = planes["cam.uv"].as_luma_alpha8().unwrap();
+        assert_eq!(uv.dimensions(), (2, 2));
+        for (i, pixel) in uv.pixels().enumerate() {
+            assert_eq!(pixel.0, [CB[i], CR[i]]);
+        }
+    }
+
+    #[test]
+    fn i420_planes() {
+        let planes = PlanarFormat::I420
+            .planes("cam", WIDTH, HEIGHT, &i420())
+            .unwrap();
+        assert_eq!(planes.len(), 3);
+        assert_eq!(raw(&planes["cam.y"]), Y);
+        for (name, plane) in [("cam.u", CB), ("cam.v", CR)] {
+            let image = &planes[name];
+            assert_eq!((image.width(), image.height()), (2, 2));
+            assert_eq!(raw(image), plane);
+        }
+    }
+
+    #[test]
+    fn wrong_frame_size() {
+        for format in [PlanarFormat::Nv12, PlanarFormat::I420] {
+            for len in [16, 18] {
+                match format.planes("cam", WIDTH, HEIGHT, &vec![0; len]) {
+                    Err(Error::Inputs(errors)) => assert!(matches!(
+                        &errors[..],
+                        [InputError::FrameSize {
+                            name,
+                            expected: 17,
+                            found,
+                        }] if name == "cam" && *found == len
+                    )),
+                    _ => panic!("a frame of {len} bytes should be rejected"),
+                }
+            }
+        }
+    }
+
+    fn to_rgb(format: PlanarFormat, frame: &[u8]) -> RgbImage {
+        let node = new_planar_input("cam".to_string(), format, WIDTH, HEIGHT).to_rgb::<u8>();
+        let outputs = HashMap::from([("out".to_string(), node.into_output())]);
+        let images = format.planes("cam", WIDTH, HEIGHT, frame).unwrap();
+        CpuTransformation::new(outputs)
+            .unwrap()
+            .call(images)
+            .unwrap()["out"]
+            .as_rgb8()
+            .unwrap()
+            .clone()
+    }
+
+    fn expected() -> RgbImage {
+        RgbImage::from_fn(WIDTH as u32, HEIGHT as u32, |col, row| {
+            let (col, row) = (col as usize, row as usize);
+            let chroma = col / 2 + row / 2 * 2;
+            let rgb = interface::Rgb::<u8>::from_pixel(YCbCr::from_video_range(
+                Y[col + row * WIDTH],
+                CB[chroma],
+                CR[chroma],
+            ));
+            image::Rgb([rgb.r, rgb.g, rgb.b])
+        })
+    }
+
+    #[test]
+    fn nv12_to_rgb() {
+        let rgb = to_rgb(PlanarFormat::Nv12, &nv12());
+        assert_eq!(rgb, expected());
+        // video range black and white with neutral chroma
+        assert_eq!(rgb.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn i420_to_rgb() {
+        assert_eq!(to_rgb(PlanarFormat::I420, &i420()), expected());
+    }
+}
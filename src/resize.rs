@@ -0,0 +1,200 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::pixel::PixelType;
+
+// How the pixels of a resized image are sampled from the pixels they cover
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ResizeFilter {
+    // The pixel under the centre of the pixel
+    Nearest,
+    // The four pixels around the centre of the pixel, weighted by their distance to it. The edge pixels are repeated
+    // outside of the image. Unlike a triangle filter, the support is not widened when downscaling, so pixels are skipped
+    // when shrinking to less than half the size.
+    Bilinear,
+    // The mean of the pixels the pixel covers, weighted by how much of them it covers, which avoids aliasing when
+    // downscaling
+    Area,
+}
+
+impl ResizeFilter {
+    // Samples the pixel at (col, row) of the resized image with `read`, a `Fn(usize, usize) -> Option<P>` reading the
+    // image, within a closure returning Option<P>. The position must be inside the resized image.
+    pub(crate) fn sample(
+        &self,
+        pixel_type: PixelType,
+        read: &syn::Ident,
+        width_in: TokenStream,
+        height_in: TokenStream,
+        width: TokenStream,
+        height: TokenStream,
+    ) -> TokenStream {
+        let interpolate = quote! {<#pixel_type as interface::Interpolate>};
+        let scale = quote! {
+            let scale_x = #width_in as f32 / #width as f32;
+            let scale_y = #height_in as f32 / #height as f32;
+        };
+
+        match self {
+            ResizeFilter::Nearest => quote! {{
+                #scale
+                let col_in = ((col as f32 + 0.5) * scale_x) as usize;
+                let row_in = ((row as f32 + 0.5) * scale_y) as usize;
+                #read(col_in.min(#width_in - 1), row_in.min(#height_in - 1))
+            }},
+
+            ResizeFilter::Bilinear => quote! {{
+                #scale
+                // the centre of the pixel in the image, where the centres of its pixels are at whole coordinates
+                let x = ((col as f32 + 0.5) * scale_x - 0.5).max(0.0);
+                let y = ((row as f32 + 0.5) * scale_y - 0.5).max(0.0);
+                let col_0 = (x as usize).min(#width_in - 1);
+                let row_0 = (y as usize).min(#height_in - 1);
+                let col_1 = (col_0 + 1).min(#width_in - 1);
+                let row_1 = (row_0 + 1).min(#height_in - 1);
+                let fx = x - col_0 as f32;
+                let fy = y - row_0 as f32;
+
+                let mut sum = <#interpolate::Sum as Default>::default();
+                sum = #interpolate::add_weighted(sum, #read(col_0, row_0)?, (1.0 - fx) * (1.0 - fy));
+                sum = #interpolate::add_weighted(sum, #read(col_1, row_0)?, fx * (1.0 - fy));
+                sum = #interpolate::add_weighted(sum, #read(col_0, row_1)?, (1.0 - fx) * fy);
+                sum = #interpolate::add_weighted(sum, #read(col_1, row_1)?, fx * fy);
+                Some(#interpolate::from_sum(sum))
+            }},
+
+            ResizeFilter::Area => quote! {{
+                #scale
+                // the pixel as a box in the image
+                let (x_0, x_1) = (col as f32 * scale_x, (col + 1) as f32 * scale_x);
+                let (y_0, y_1) = (row as f32 * scale_y, (row + 1) as f32 * scale_y);
+
+                let mut sum = <#interpolate::Sum as Default>::default();
+                let mut row_in = y_0 as usize;
+                while row_in < #height_in && (row_in as f32) < y_1 {
+                    let weight_y = (y_1.min((row_in + 1) as f32) - y_0.max(row_in as f32)) / scale_y;
+                    let mut col_in = x_0 as usize;
+                    while col_in < #width_in && (col_in as f32) < x_1 {
+                        let weight_x = (x_1.min((col_in + 1) as f32) - x_0.max(col_in as f32)) / scale_x;
+                        sum = #interpolate::add_weighted(sum, #read(col_in, row_in)?, weight_x * weight_y);
+                        col_in += 1;
+                    }
+                    row_in += 1;
+                }
+                Some(#interpolate::from_sum(sum))
+            }},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::{imageops, DynamicImage, RgbImage};
+    use interface::Rgb;
+
+    use super::ResizeFilter;
+    use crate::{new_input, CpuTransformation};
+
+    const WIDTH: u32 = 7;
+    const HEIGHT: u32 = 5;
+
+    fn image() -> RgbImage {
+        RgbImage::from_fn(WIDTH, HEIGHT, |col, row| {
+            image::Rgb([
+                ((col * 37 + row * 91) % 256) as u8,
+                ((col * 53 + row * 17 + 100) % 256) as u8,
+                ((col * row * 29 + 7) % 256) as u8,
+            ])
+        })
+    }
+
+    fn resize(image: &RgbImage, width: u32, height: u32, filter: ResizeFilter) -> RgbImage {
+        let node = new_input::<Rgb<u8>>(
+            "img".to_string(),
+            image.width() as usize,
+            image.height() as usize,
+        )
+        .resize(width as usize, height as usize, filter);
+        let outputs = HashMap::from([("out".to_string(), node.into_output())]);
+        let images = HashMap::from([("img".to_string(), DynamicImage::ImageRgb8(image.clone()))]);
+        CpuTransformation::new(outputs)
+            .unwrap()
+            .call(images)
+            .unwrap()["out"]
+            .as_rgb8()
+            .unwrap()
+            .clone()
+    }
+
+    // Asserts that no channel differs by more than the tolerance
+    fn assert_close(result: &RgbImage, expected: &RgbImage, tolerance: u8) {
+        assert_eq!(result.dimensions(), expected.dimensions());
+        for (col, row, pixel) in result.enumerate_pixels() {
+            let expected_pixel = expected.get_pixel(col, row);
+            for (a, b) in pixel.0.iter().zip(expected_pixel.0) {
+                assert!(
+                    a.abs_diff(b) <= tolerance,
+                    "pixel ({col}, {row}) is {pixel:?}, but {expected_pixel:?} was expected"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_matches_imageops() {
+        for (width, height) in [(16, 11), (3, 2), (10, 3)] {
+            let expected = imageops::resize(&image(), width, height, imageops::FilterType::Nearest);
+            assert_close(
+                &resize(&image(), width, height, ResizeFilter::Nearest),
+                &expected,
+                0,
+            );
+        }
+    }
+
+    // The triangle filter of imageops widens its support when downscaling, so only upscaling is compared. Channels may
+    // be off by one, since imageops filters rows and columns in separate passes and values near a half can round
+    // either way.
+    #[test]
+    fn bilinear_upscaling_matches_imageops() {
+        for (width, height) in [(16, 11), (14, 10), (9, 8)] {
+            let expected =
+                imageops::resize(&image(), width, height, imageops::FilterType::Triangle);
+            assert_close(
+                &resize(&image(), width, height, ResizeFilter::Bilinear),
+                &expected,
+                1,
+            );
+        }
+    }
+
+    // By whole factors, the thumbnail of imageops is the mean of the covered pixels too. Channels may be off by one,
+    // since it averages in integers and exact halves can round the other way in f32.
+    #[test]
+    fn area_downscaling_matches_imageops() {
+        // the image tiled to a size with many whole factors
+        let tile = image();
+        let image = RgbImage::from_fn(12, 6, |col, row| *tile.get_pixel(col % WIDTH, row % HEIGHT));
+        for (width, height) in [(6, 3), (4, 2), (3, 3), (12, 2)] {
+            let expected = imageops::thumbnail(&image, width, height);
+            assert_close(
+                &resize(&image, width, height, ResizeFilter::Area),
+                &expected,
+                1,
+            );
+        }
+    }
+
+    #[test]
+    fn same_size_is_unchanged() {
+        for filter in [
+            ResizeFilter::Nearest,
+            ResizeFilter::Bilinear,
+            ResizeFilter::Area,
+        ] {
+            assert_close(&resize(&image(), WIDTH, HEIGHT, filter), &image(), 0);
+        }
+    }
+}
@@ -1,114 +1,124 @@
-use std::{cell::UnsafeCell, collections::HashMap, rc::Rc};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    rc::Rc,
+};
 
 use cuda::{
     graph::{DevicePtr, ExecutableGraph, Graph, MemCpyDirection},
-    module::Module,
+    module::{Function, Module},
     stream::Stream,
-    Cuda, Result,
+    Cuda,
 };
 use image::DynamicImage;
-use interface::Rgb;
 use itertools::Itertools;
 
 use crate::{
-    codegen,
+    buffer::{check_inputs, Buffer, RequiredInput},
+    codegen::{self, Kernel, LaunchGeometry, Target},
     compiler::compile,
     computational_dependency_graph as cdg,
-    pixel::{Pixel, PixelType},
+    deduplication::deduplicate,
+    fusion,
+    pixel::PixelType,
+    Error, Result,
 };
-use cdg::{toposort, Node, Operation};
-
-struct Buffer {
-    width: usize,
-    height: usize,
-    pixel_type: PixelType,
-    inner: Rc<Box<UnsafeCell<[u8]>>>,
+use cdg::{toposort, validate, Node, Operation, Output};
+use fusion::{kernel_dependencies, kernel_nodes, materialized_nodes};
+
+// Limits of every device since compute capability 2.0. Kernels using more than 48KB of shared memory have to opt in.
+const MAX_THREADS_PER_BLOCK: usize = 1024;
+const MAX_SHARED_MEMORY: usize = 48 * 1024;
+
+/// A transformation compiled for the device, whose kernels are launched as one cuda graph.
+///
+/// Building a transformation compiles its kernels to ptx and caches the ptx on disk, so that later builds of the same
+/// kernels skip the compilation. The cache is written to `$XDG_CACHE_HOME/cuda-fusion`, or `~/.cache/cuda-fusion` if
+/// XDG_CACHE_HOME is not set. Set CUDA_FUSION_CACHE_DIR to use another directory, or to an empty string to disable the
+/// cache.
+pub struct Transformation<'a> {
+    input_buffers: HashMap<String, Buffer>,
+    output_buffers: HashMap<String, Buffer>,
+    launch_geometries: Vec<LaunchGeometry>,
+    executable_graph: ExecutableGraph<'a>,
+    stream: Stream<'a>,
 }
 
-type ImageBuffer<P> = image::ImageBuffer<
-    <P as Pixel>::ImageCratePixel,
-    Vec<<<P as Pixel>::ImageCratePixel as image::Pixel>::Subpixel>,
->;
-
-impl Buffer {
-    unsafe fn copy_from_dynamic_image(&mut self, image: &DynamicImage) {
-        assert_eq!(self.height, image.height() as usize);
-        assert_eq!(self.width, image.width() as usize);
-
-        fn copy<P: Pixel>(src: &ImageBuffer<P>, dst: &mut Buffer) {
-            assert_eq!(dst.pixel_type, P::ty());
-            let dst_slice = unsafe {
-                std::slice::from_raw_parts_mut(dst.inner.get() as *mut P, dst.height * dst.width)
-            };
+// Configures how the kernels of a transformation are launched. The block size of a node applies to the kernel computing it,
+// which may be the kernel of a consumer it is fused into, and overrides the block size of the builder.
+pub struct TransformationBuilder {
+    outputs: HashMap<String, Output>,
+    block_size: (usize, usize),
+    node_block_sizes: Vec<(Rc<cdg::Node>, (usize, usize))>,
+}
 
-            for (src_px, dst_px) in std::iter::zip(src.pixels(), dst_slice) {
-                *dst_px = P::from_image_crate_pixel(*src_px);
-            }
-        }
+impl TransformationBuilder {
+    pub fn block_size(mut self, block_width: usize, block_height: usize) -> Self {
+        self.block_size = (block_width, block_height);
+        self
+    }
 
-        match image {
-            DynamicImage::ImageRgb8(ib) => copy::<Rgb<u8>>(ib, self),
-            DynamicImage::ImageRgb32F(ib) => copy::<Rgb<f32>>(ib, self),
-            _ => unimplemented!(),
-        };
+    pub fn node_block_size<P>(
+        mut self,
+        node: &crate::Node<P>,
+        block_width: usize,
+        block_height: usize,
+    ) -> Self {
+        self.node_block_sizes
+            .push((node.inner.clone(), (block_width, block_height)));
+        self
     }
 
-    unsafe fn to_dynamic_image(&self) -> DynamicImage {
-        fn to_image_buffer<P: Pixel>(buffer: &Buffer) -> ImageBuffer<P> {
-            assert_eq!(buffer.pixel_type, P::ty());
-            let pixels = unsafe {
-                std::slice::from_raw_parts(
-                    buffer.inner.get() as *const P,
-                    buffer.height * buffer.width,
-                )
-            };
+    pub fn build<'a>(self, cuda: &'a Cuda) -> Result<Transformation<'a>> {
+        let TransformationBuilder {
+            outputs,
+            block_size,
+            node_block_sizes,
+        } = self;
 
-            ImageBuffer::<P>::from_fn(buffer.width as u32, buffer.height as u32, |x, y| {
-                pixels[x as usize + y as usize * buffer.width].into_image_crate_pixel()
-            })
-        }
+        let outputs: HashMap<String, &Node> = outputs
+            .iter()
+            .map(|(name, output)| (name.clone(), output.node()))
+            .collect();
 
-        match self.pixel_type {
-            PixelType::RgbU8 => DynamicImage::ImageRgb8(to_image_buffer::<Rgb<u8>>(self)),
-            PixelType::RgbF32 => DynamicImage::ImageRgb32F(to_image_buffer::<Rgb<f32>>(self)),
-        }
-    }
-}
+        // validated before deduplication, which would merge identical inputs of the same name
+        validate(&outputs).map_err(Error::InvalidGraph)?;
 
-pub struct Output(Rc<cdg::Node>);
+        // identical sub-pipelines are merged before anything is allocated or compiled for them
+        let deduplicated = deduplicate(&outputs);
+        let outputs: HashMap<String, &Node> = outputs
+            .into_iter()
+            .map(|(name, node)| (name, &*deduplicated[&(node as *const _)]))
+            .collect();
 
-impl Output {
-    pub fn new(node: Rc<cdg::Node>) -> Self {
-        Self(node)
-    }
-}
+        // nodes no output depends on are not computed, so their block sizes are ignored
+        let node_block_sizes = node_block_sizes
+            .iter()
+            .filter_map(|(node, size)| {
+                Some((Rc::as_ptr(deduplicated.get(&Rc::as_ptr(node))?), *size))
+            })
+            .collect_vec();
 
-pub struct Transformation<'a> {
-    input_buffers: HashMap<String, Buffer>,
-    output_buffers: HashMap<String, Buffer>,
-    executable_graph: ExecutableGraph<'a>,
-    stream: Stream<'a>,
-}
+        let materialized = materialized_nodes(outputs.values().copied().collect_vec());
+        let nodes = toposort(outputs.values().copied().collect_vec());
+        let block_sizes = kernel_block_sizes(&nodes, &materialized, block_size, &node_block_sizes)?;
 
-impl<'a> Transformation<'a> {
-    pub fn new(cuda: &'a Cuda, outputs: HashMap<String, Output>) -> Result<Self> {
-        let graph = Graph::new(&cuda).unwrap();
+        let graph = Graph::new(cuda)?;
 
         let alignment = cuda.get_alignment()?;
 
         let mut input_buffers = HashMap::new();
         let mut graph_nodes: HashMap<*const Node, cuda::graph::Node> = HashMap::new();
         let mut device_ptrs: HashMap<*const Node, DevicePtr> = HashMap::new();
+        let mut functions: HashMap<syn::ItemFn, Function> = HashMap::new();
+        let mut launch_geometries = Vec::new();
 
-        let outputs: HashMap<String, &Node> = outputs
-            .iter()
-            .map(|(name, Output(node))| (name.clone(), &**node))
-            .collect();
-
-        for node in toposort(outputs.values().copied().collect_vec()) {
-            let (alloc_node, device_ptr) = graph
-                .add_mem_alloc_node(node.height(), node.pitch(alignment))
-                .unwrap();
+        // nodes that are not materialized are computed inside the kernels of their consumers
+        for node in nodes
+            .into_iter()
+            .filter(|node| materialized.contains(&(*node as *const _)))
+        {
+            let (alloc_node, device_ptr) =
+                graph.add_mem_alloc_node(node.height(), node.pitch(alignment))?;
 
             let graph_node = match node {
                 Node::Input {
@@ -117,17 +127,15 @@ impl<'a> Transformation<'a> {
                     height,
                     pixel_type,
                 } => {
-                    let (graph_node, buffer) = graph
-                        .add_mem_cpy_node(
-                            &alloc_node,
-                            MemCpyDirection::HostToDevice,
-                            *width,
-                            *height,
-                            node.pitch(alignment),
-                            pixel_type.layout(),
-                            device_ptr,
-                        )
-                        .unwrap();
+                    let (graph_node, buffer) = graph.add_mem_cpy_node(
+                        &alloc_node,
+                        MemCpyDirection::HostToDevice,
+                        *width,
+                        *height,
+                        node.pitch(alignment),
+                        pixel_type.layout(),
+                        device_ptr,
+                    )?;
                     let buffer = Buffer {
                         inner: buffer,
                         width: *width,
@@ -138,139 +146,49 @@ impl<'a> Transformation<'a> {
                     graph_node
                 }
 
-                Node::Operation(operation) => {
-                    let block_width = 16;
-                    let block_height = 16;
-
-                    let f = match operation {
-                        Operation::MapPixel {
-                            dependency,
-                            f,
-                            pixel_type,
-                        } => codegen::map_pixel(
-                            device_ptrs[&Rc::as_ptr(&dependency)].inner(),
-                            device_ptr.inner(),
-                            dependency.width(),
-                            dependency.height(),
-                            dependency.pitch(alignment),
-                            node.pitch(alignment),
-                            dependency.pixel_type(),
-                            *pixel_type,
-                            f,
-                            block_width,
-                            block_height,
-                        ),
-
-                        Operation::MapPatch {
-                            dependency,
-                            f,
-                            dimension,
-                            pixel_type,
-                        } => codegen::map_patch(
-                            device_ptrs[&Rc::as_ptr(&dependency)].inner(),
-                            device_ptr.inner(),
-                            dependency.width(),
-                            dependency.height(),
-                            dependency.pitch(alignment),
-                            node.pitch(alignment),
-                            dependency.pixel_type(),
-                            *pixel_type,
-                            f,
-                            *dimension,
-                            block_width,
-                            block_height,
-                        ),
-
-                        Operation::MapImage {
-                            dependency,
-                            f,
-                            height,
-                            width,
-                            pixel_type,
-                        } => codegen::map_image(
-                            device_ptrs[&Rc::as_ptr(&dependency)].inner(),
-                            device_ptr.inner(),
-                            dependency.width(),
-                            *width,
-                            dependency.height(),
-                            *height,
-                            dependency.pitch(alignment),
-                            node.pitch(alignment),
-                            dependency.pixel_type(),
-                            *pixel_type,
-                            f,
-                            block_width,
-                            block_height,
-                        ),
-
-                        Operation::Flip { dependency } => codegen::flip(
-                            device_ptrs[&Rc::as_ptr(&dependency)].inner(),
-                            device_ptr.inner(),
-                            dependency.width(),
-                            dependency.height(),
-                            dependency.pitch(alignment),
-                            dependency.pixel_type(),
-                            block_width,
-                            block_height,
-                        ),
-
-                        Operation::HConcat {
-                            dependency_left,
-                            dependency_right,
-                        } => codegen::h_concat(
-                            device_ptrs[&Rc::as_ptr(&dependency_left)].inner(),
-                            device_ptrs[&Rc::as_ptr(&dependency_right)].inner(),
-                            device_ptr.inner(),
-                            dependency_left.width(),
-                            dependency_right.width(),
-                            node.width(),
-                            node.height(),
-                            dependency_left.pitch(alignment),
-                            dependency_right.pitch(alignment),
-                            node.pitch(alignment),
-                            node.pixel_type(),
-                            block_width,
-                            block_height,
-                        ),
-
-                        Operation::VConcat {
-                            dependency_top,
-                            dependency_bottom,
-                        } => codegen::v_concat(
-                            device_ptrs[&Rc::as_ptr(&dependency_top)].inner(),
-                            device_ptrs[&Rc::as_ptr(&dependency_bottom)].inner(),
-                            device_ptr.inner(),
-                            node.width(),
-                            dependency_top.height(),
-                            dependency_bottom.height(),
-                            node.height(),
-                            node.pitch(alignment),
-                            node.pixel_type(),
-                            block_width,
-                            block_height,
-                        ),
+                Node::Operation(_) => {
+                    let (block_width, block_height) = block_sizes[&(node as *const _)];
+                    let Kernel {
+                        item_fn,
+                        params,
+                        geometry,
+                    } = codegen::kernel(
+                        node,
+                        device_ptr.inner(),
+                        |n| device_ptrs.get(&(n as *const _)).map(DevicePtr::inner),
+                        alignment,
+                        block_width,
+                        block_height,
+                        Target::Device,
+                    );
+
+                    // nodes of the same structure share a kernel, which only differs in its parameters
+                    let function = match functions.entry(item_fn) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let module = Module::from_ptx(&compile(entry.key().clone())?)
+                                .map_err(Error::PtxLoad)?;
+                            entry.insert(module.get_function("kernel").map_err(Error::PtxLoad)?)
+                        }
                     };
 
-                    let module = Module::from_ptx(&compile(f).unwrap()).unwrap();
-                    let function = module.get_function("kernel").unwrap();
-
-                    let dependencies = node
-                        .dependencies()
+                    let dependencies = kernel_dependencies(node, &materialized)
                         .into_iter()
                         .map(|n| graph_nodes.get(&(n as *const _)).unwrap())
                         .chain(std::iter::once(&alloc_node))
                         .collect_vec();
 
-                    graph
-                        .add_kernel_node(
-                            &dependencies,
-                            &function,
-                            block_width,
-                            block_height,
-                            160,
-                            140,
-                        )
-                        .unwrap()
+                    let graph_node = graph.add_kernel_node(
+                        &dependencies,
+                        function,
+                        geometry.block_width,
+                        geometry.block_height,
+                        geometry.grid_width,
+                        geometry.grid_height,
+                        &params,
+                    )?;
+                    launch_geometries.push(geometry);
+                    graph_node
                 }
             };
 
@@ -296,33 +214,159 @@ impl<'a> Transformation<'a> {
                     width: node.width(),
                     pixel_type: node.pixel_type(),
                 };
-                Ok((name, buffer))
+                Ok::<_, Error>((name, buffer))
             })
             .try_collect()?;
 
-        Ok(Self {
+        Ok(Transformation {
             input_buffers,
             output_buffers,
+            launch_geometries,
             executable_graph: graph.make_executable()?,
-            stream: Stream::new(&cuda)?,
+            stream: Stream::new(cuda)?,
         })
     }
+}
+
+// Chooses the block size of the kernel computing each materialized operation, checked against the limits of the device so
+// that invalid block sizes are reported before anything is allocated or compiled
+fn kernel_block_sizes(
+    nodes: &[&Node],
+    materialized: &HashSet<*const Node>,
+    block_size: (usize, usize),
+    node_block_sizes: &[(*const Node, (usize, usize))],
+) -> Result<HashMap<*const Node, (usize, usize)>> {
+    nodes
+        .iter()
+        .filter(|node| {
+            matches!(node, Node::Operation(_)) && materialized.contains(&(**node as *const _))
+        })
+        .map(|node| {
+            let kernel_nodes = kernel_nodes(node, materialized);
+            let kernel_node_ptrs: HashSet<*const Node> =
+                kernel_nodes.iter().map(|n| *n as *const _).collect();
+            let block_sizes = node_block_sizes
+                .iter()
+                .filter(|(n, _size)| kernel_node_ptrs.contains(n))
+                .map(|(_n, size)| *size)
+                .unique()
+                .collect_vec();
+            let (block_width, block_height) = match block_sizes.as_slice() {
+                [] => block_size,
+                [size] => *size,
+                [(block_width, block_height), (other_width, other_height), ..] => {
+                    return Err(Error::InvalidBlockSize {
+                        block_width: *block_width,
+                        block_height: *block_height,
+                        reason: format!(
+                            "another node fused into the same kernel has the block size {other_width}x{other_height}"
+                        ),
+                    })
+                }
+            };
+
+            // a kernel contains at most one map_patch, whose dependency is loaded into the shared memory tile
+            let patch = kernel_nodes.iter().find_map(|node| match node {
+                Node::Operation(Operation::MapPatch {
+                    dependency,
+                    f: _,
+                    patch_width,
+                    patch_height,
+                    pixel_type: _,
+                    border: _,
+                }) => Some((*patch_width, *patch_height, dependency.pixel_type())),
+                _ => None,
+            });
+            check_block_size(block_width, block_height, patch)?;
+
+            Ok((*node as *const _, (block_width, block_height)))
+        })
+        .collect()
+}
+
+// Checks the block size against the limits of the device, for a kernel with the patch size and pixel type of its
+// map_patch if it has one
+fn check_block_size(
+    block_width: usize,
+    block_height: usize,
+    patch: Option<(usize, usize, PixelType)>,
+) -> Result<()> {
+    let error = |reason: String| {
+        Err(Error::InvalidBlockSize {
+            block_width,
+            block_height,
+            reason,
+        })
+    };
+
+    if block_width == 0 || block_height == 0 {
+        return error("blocks must contain at least one thread".to_string());
+    }
+    if block_width * block_height > MAX_THREADS_PER_BLOCK {
+        return error(format!(
+            "blocks can contain at most {MAX_THREADS_PER_BLOCK} threads"
+        ));
+    }
+
+    if let Some((patch_width, patch_height, pixel_type)) = patch {
+        // the tile is the block surrounded by the halo of its patches
+        let tile_width = block_width + patch_width - 1;
+        let tile_height = block_height + patch_height - 1;
+        let shared_memory = tile_width * tile_height * pixel_type.layout().size();
+        if shared_memory > MAX_SHARED_MEMORY {
+            return error(format!(
+                "the {tile_width}x{tile_height} tile of {pixel_type:?} pixels needs {shared_memory} bytes of shared memory, \
+                 but at most {MAX_SHARED_MEMORY} are available"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> Transformation<'a> {
+    pub fn new(cuda: &'a Cuda, outputs: HashMap<String, Output>) -> Result<Self> {
+        Self::builder(outputs).build(cuda)
+    }
+
+    pub fn builder(outputs: HashMap<String, Output>) -> TransformationBuilder {
+        TransformationBuilder {
+            outputs,
+            block_size: (16, 16),
+            node_block_sizes: Vec::new(),
+        }
+    }
+
+    // The inputs every call must be given images for, sorted by name
+    pub fn inputs(&self) -> Vec<RequiredInput> {
+        self.input_buffers
+            .iter()
+            .sorted_by_key(|(name, _buffer)| *name)
+            .map(|(name, buffer)| buffer.required_input(name))
+            .collect()
+    }
+
+    // The launch geometry of every kernel, in the order they are added to the graph
+    pub fn launch_geometries(&self) -> &[LaunchGeometry] {
+        &self.launch_geometries
+    }
 
     pub fn call(
         &mut self,
         inputs: HashMap<String, DynamicImage>,
-    ) -> cuda::Result<HashMap<String, DynamicImage>> {
+    ) -> Result<HashMap<String, DynamicImage>> {
+        check_inputs(&self.input_buffers, &inputs)?;
+
         for (name, buffer) in self.input_buffers.iter_mut() {
-            let image = inputs.get(name).unwrap();
             // Safe because the buffer is currently not being read from
             unsafe {
-                buffer.copy_from_dynamic_image(image);
+                buffer.copy_from_dynamic_image(&inputs[name]);
             }
         }
 
         // Safe if the kernels are written correctly
-        unsafe { self.executable_graph.launch(&mut self.stream).unwrap() };
-        self.stream.synchronize().unwrap();
+        unsafe { self.executable_graph.launch(&self.stream)? };
+        self.stream.synchronize()?;
 
         // Safe because the buffers are not being written to
         unsafe {
@@ -334,3 +378,139 @@ impl<'a> Transformation<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use interface::{Patch, Rgb, Rgba};
+    use itertools::Itertools;
+    use macros::{map_patch_kernel, map_pixel_kernel};
+
+    use super::kernel_block_sizes;
+    use crate::{
+        computational_dependency_graph::{toposort, Node},
+        fusion::materialized_nodes,
+        new_input, Error, Result,
+    };
+
+    #[map_pixel_kernel]
+    fn invert(px: Rgb<u8>) -> Rgb<u8> {
+        interface::Rgb {
+            r: 255 - px.r,
+            g: 255 - px.g,
+            b: 255 - px.b,
+        }
+    }
+
+    #[map_pixel_kernel]
+    fn to_rgba(px: Rgb<u8>) -> Rgba<f32> {
+        interface::Rgba {
+            r: px.r as f32,
+            g: px.g as f32,
+            b: px.b as f32,
+            a: 1.0,
+        }
+    }
+
+    #[map_patch_kernel]
+    fn wide(patch: Patch<65, 3, Rgba<f32>>) -> Rgba<f32> {
+        patch.at(0, 0)
+    }
+
+    // The block sizes of the kernels computing the root, in topological order
+    fn block_sizes(
+        root: &Rc<Node>,
+        block_size: (usize, usize),
+        node_block_sizes: &[(&Rc<Node>, (usize, usize))],
+    ) -> Result<Vec<(usize, usize)>> {
+        let nodes = toposort(vec![root]);
+        let materialized = materialized_nodes(vec![root]);
+        let node_block_sizes = node_block_sizes
+            .iter()
+            .map(|(node, size)| (Rc::as_ptr(node), *size))
+            .collect_vec();
+        let block_sizes = kernel_block_sizes(&nodes, &materialized, block_size, &node_block_sizes)?;
+        Ok(nodes
+            .iter()
+            .filter_map(|node| block_sizes.get(&(*node as *const _)).copied())
+            .collect())
+    }
+
+    fn invalid_block_size(result: Result<Vec<(usize, usize)>>) -> (usize, usize, String) {
+        match result {
+            Err(Error::InvalidBlockSize {
+                block_width,
+                block_height,
+                reason,
+            }) => (block_width, block_height, reason),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("expected the block size to be rejected"),
+        }
+    }
+
+    #[test]
+    fn node_block_size_overrides_the_default() {
+        let first = new_input::<Rgb<u8>>("img".into(), 8, 8).map_pixel(&invert);
+        let out = first.map_pixel(&invert);
+        assert_eq!(block_sizes(&out.inner, (16, 16), &[]).unwrap(), [(16, 16)]);
+        assert_eq!(
+            block_sizes(&out.inner, (16, 16), &[(&first.inner, (8, 4))]).unwrap(),
+            [(8, 4)]
+        );
+    }
+
+    #[test]
+    fn at_most_1024_threads() {
+        let out = new_input::<Rgb<u8>>("img".into(), 8, 8).map_pixel(&invert);
+        assert_eq!(block_sizes(&out.inner, (32, 32), &[]).unwrap(), [(32, 32)]);
+        assert_eq!(
+            invalid_block_size(block_sizes(&out.inner, (64, 32), &[])),
+            (
+                64,
+                32,
+                "blocks can contain at most 1024 threads".to_string()
+            )
+        );
+        assert_eq!(
+            invalid_block_size(block_sizes(&out.inner, (0, 16), &[])),
+            (0, 16, "blocks must contain at least one thread".to_string())
+        );
+    }
+
+    #[test]
+    fn at_most_48kb_of_shared_memory() {
+        let out = new_input::<Rgb<u8>>("img".into(), 8, 8)
+            .map_pixel(&to_rgba)
+            .map_patch(&wide);
+        // the 96x32 tile of 16 byte pixels takes exactly 48KB
+        assert_eq!(block_sizes(&out.inner, (32, 30), &[]).unwrap(), [(32, 30)]);
+        assert_eq!(
+            invalid_block_size(block_sizes(&out.inner, (32, 31), &[])),
+            (
+                32,
+                31,
+                "the 96x33 tile of RgbaF32 pixels needs 50688 bytes of shared memory, but at most 49152 are available"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn conflicting_block_sizes_in_a_fused_kernel() {
+        let first = new_input::<Rgb<u8>>("img".into(), 8, 8).map_pixel(&invert);
+        let out = first.map_pixel(&invert);
+        assert_eq!(
+            invalid_block_size(block_sizes(
+                &out.inner,
+                (16, 16),
+                &[(&first.inner, (8, 8)), (&out.inner, (16, 8))]
+            )),
+            (
+                8,
+                8,
+                "another node fused into the same kernel has the block size 16x8".to_string()
+            )
+        );
+    }
+}
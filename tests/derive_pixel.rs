@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use cuda_fusion::{new_input, Border, CpuTransformation};
+use image::{DynamicImage, RgbImage};
+use interface::{Patch, Rgb};
+use macros::{map_patch_kernel, map_pixel_kernel, Pixel};
+
+// A pixel with three padding bytes after conf
+#[repr(C)]
+#[derive(Clone, Copy, Pixel)]
+struct Depth {
+    z: f32,
+    conf: u8,
+}
+
+#[map_pixel_kernel]
+fn to_depth(px: Rgb<u8>) -> Depth {
+    Depth {
+        z: px.r as f32 * 0.5,
+        conf: px.g,
+    }
+}
+
+#[map_patch_kernel]
+fn right_neighbour(patch: Patch<3, 1, Depth>) -> Rgb<u8> {
+    let depth = patch.at(1, 0);
+    interface::Rgb {
+        r: (depth.z * 2.0) as u8,
+        g: depth.conf,
+        b: 0,
+    }
+}
+
+fn run(node: cuda_fusion::Node<Rgb<u8>>, image: RgbImage) -> RgbImage {
+    let outputs = HashMap::from([("out".to_string(), node.into_output())]);
+    let mut transformation = CpuTransformation::new(outputs).unwrap();
+    let inputs = HashMap::from([("img".to_string(), DynamicImage::ImageRgb8(image))]);
+    let mut outputs = transformation.call(inputs).unwrap();
+    outputs.remove("out").unwrap().into_rgb8()
+}
+
+fn image() -> RgbImage {
+    RgbImage::from_fn(5, 4, |col, row| {
+        image::Rgb([(col * 40) as u8, (row * 60) as u8, 7])
+    })
+}
+
+#[test]
+fn derived_pixel_between_kernels() {
+    let image = image();
+    let expected = RgbImage::from_fn(5, 4, |col, row| {
+        let [r, g, _] = image.get_pixel((col + 1).min(4), row).0;
+        image::Rgb([r, g, 0])
+    });
+    let node = new_input::<Rgb<u8>>("img".into(), 5, 4)
+        .map_pixel(&to_depth)
+        .map_patch_with_border(&right_neighbour, Border::Replicate);
+    assert_eq!(run(node, image), expected);
+}
+
+#[test]
+fn constant_border_of_derived_pixel() {
+    // the constant is copied field by field, with its padding bytes zeroed
+    let border = Depth { z: 50.0, conf: 9 };
+    let image = image();
+    let expected = RgbImage::from_fn(5, 4, |col, row| match col {
+        4 => image::Rgb([100, 9, 0]),
+        _ => {
+            let [r, g, _] = image.get_pixel(col + 1, row).0;
+            image::Rgb([r, g, 0])
+        }
+    });
+    let node = new_input::<Rgb<u8>>("img".into(), 5, 4)
+        .map_pixel(&to_depth)
+        .map_patch_with_border(&right_neighbour, Border::Constant(border));
+    assert_eq!(run(node, image), expected);
+}
+
+#[test]
+fn rejected_structs() {
+    trybuild::TestCases::new().compile_fail("tests/ui/derive_pixel_*.rs");
+}
@@ -0,0 +1,10 @@
+use macros::Pixel;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pixel)]
+struct Depth {
+    z: f32,
+    conf: [u8; 2],
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use macros::Pixel;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pixel)]
+struct Depth(f32, u8);
+
+fn main() {}
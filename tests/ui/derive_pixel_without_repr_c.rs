@@ -0,0 +1,9 @@
+use macros::Pixel;
+
+#[derive(Clone, Copy, Pixel)]
+struct Depth {
+    z: f32,
+    conf: u8,
+}
+
+fn main() {}